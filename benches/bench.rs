@@ -1,6 +1,8 @@
-use coi::{container, Inject};
-use criterion::{criterion_group, criterion_main, Criterion};
+use coi::{container, Container, ContainerBuilder, Inject, Registration, RegistrationKind};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 macro_rules! make_deep_container {
     ($($scope_type:ident)?) => {
@@ -376,6 +378,132 @@ fn doubly_scoped_container_wide_scoped_dependencies(c: &mut Criterion) {
     });
 }
 
+const THREADS: usize = 8;
+
+/// Times `THREADS` threads each calling `resolve` `iters` times on the same container.
+fn resolve_concurrently<F>(container: &Container, iters: u64, resolve: F) -> Duration
+where
+    F: Fn(&Container) + Sync,
+{
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..iters {
+                    resolve(container);
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn concurrent_deeply_nested_singleton_dependencies(c: &mut Criterion) {
+    let container = make_deep_container!(singleton);
+    c.bench_function("concurrent deeply nested singleton", |b| {
+        b.iter_custom(|iters| {
+            resolve_concurrently(&container, iters, |container| {
+                black_box(container.resolve::<dyn ID1>("d1").unwrap());
+            })
+        })
+    });
+}
+
+fn concurrent_wide_singleton_dependencies(c: &mut Criterion) {
+    let container = make_wide_container!(singleton);
+    c.bench_function("concurrent wide singleton", |b| {
+        b.iter_custom(|iters| {
+            resolve_concurrently(&container, iters, |container| {
+                black_box(container.resolve::<dyn IW1>("w1").unwrap());
+            })
+        })
+    });
+}
+
+fn concurrent_scoped_container_wide_scoped_dependencies(c: &mut Criterion) {
+    let container = make_wide_container!(scoped);
+    let container = container.scoped();
+    c.bench_function("concurrent scoped wide scoped", |b| {
+        b.iter_custom(|iters| {
+            resolve_concurrently(&container, iters, |container| {
+                black_box(container.resolve::<dyn IW1>("w1").unwrap());
+            })
+        })
+    });
+}
+
+fn concurrent_scoped_container_wide_singleton_dependencies(c: &mut Criterion) {
+    let container = make_wide_container!(singleton);
+    let container = container.scoped();
+    c.bench_function("concurrent scoped wide singleton", |b| {
+        b.iter_custom(|iters| {
+            resolve_concurrently(&container, iters, |container| {
+                black_box(container.resolve::<dyn IW1>("w1").unwrap());
+            })
+        })
+    });
+}
+
+const REGISTRATIONS: usize = 100;
+
+#[derive(Inject)]
+#[coi(provides Leaf with Leaf)]
+struct Leaf;
+
+/// A container with `REGISTRATIONS` registrations of each kind, keyed `transient0`, `scoped0`,
+/// `singleton0` and so on.
+fn make_large_container() -> Container {
+    let kinds = [
+        ("transient", RegistrationKind::Transient),
+        ("scoped", RegistrationKind::Scoped),
+        ("singleton", RegistrationKind::Singleton),
+    ];
+    let mut builder = ContainerBuilder::new();
+    for (name, kind) in kinds {
+        for i in 0..REGISTRATIONS {
+            let key = Box::leak(format!("{}{}", name, i).into_boxed_str());
+            builder = builder.register_as(key, Registration::new(kind, LeafProvider));
+        }
+    }
+    builder.build()
+}
+
+fn scope_creation(c: &mut Criterion) {
+    let container = make_large_container();
+    c.bench_function("scope creation", |b| {
+        b.iter(|| black_box(container.scoped()))
+    });
+}
+
+fn scope_creation_and_scoped_resolve(c: &mut Criterion) {
+    let container = make_large_container();
+    c.bench_function("scope creation and scoped resolve", |b| {
+        b.iter(|| {
+            let scoped = container.scoped();
+            black_box(scoped.resolve::<Leaf>("scoped0").unwrap());
+        })
+    });
+}
+
+fn scope_creation_with_registrations(c: &mut Criterion) {
+    let container = make_large_container();
+    c.bench_function("scope creation with registrations", |b| {
+        b.iter(|| {
+            let scoped = container
+                .scoped_with(|builder| builder.register_instance("request", Arc::new(Leaf)));
+            black_box(scoped.resolve::<Leaf>("request").unwrap());
+        })
+    });
+}
+
+fn nested_scope_creation(c: &mut Criterion) {
+    let container = make_large_container();
+    let scoped = container.scoped();
+    c.bench_function("nested scope creation", |b| {
+        b.iter(|| black_box(scoped.scoped()))
+    });
+}
+
 criterion_group!(simple, a_simple_resolve);
 criterion_group!(
     deeply_nested,
@@ -413,6 +541,20 @@ criterion_group!(
     doubly_scoped_container_wide_singleton_dependencies,
     doubly_scoped_container_wide_scoped_dependencies
 );
+criterion_group!(
+    concurrent,
+    concurrent_deeply_nested_singleton_dependencies,
+    concurrent_wide_singleton_dependencies,
+    concurrent_scoped_container_wide_scoped_dependencies,
+    concurrent_scoped_container_wide_singleton_dependencies
+);
+criterion_group!(
+    scope_creation_cost,
+    scope_creation,
+    scope_creation_and_scoped_resolve,
+    scope_creation_with_registrations,
+    nested_scope_creation
+);
 criterion_main!(
     simple,
     deeply_nested,
@@ -420,7 +562,9 @@ criterion_main!(
     scoped_deeply_nested,
     scoped_wide,
     double_scoped_deeply_nested,
-    double_scoped_wide
+    double_scoped_wide,
+    concurrent,
+    scope_creation_cost
 );
 
 macro_rules! make_dep {
@@ -437,6 +581,7 @@ macro_rules! make_dep {
         }
 
         impl $struct {
+            #[allow(clippy::too_many_arguments)]
             fn new($($dep_name: Arc<dyn $dep_trait>),*) -> Self {
                 Self { $($dep_name,)* }
             }
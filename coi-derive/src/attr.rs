@@ -1,12 +1,12 @@
 use crate::ctxt::Ctxt;
 use crate::symbol::*;
 use proc_macro2::TokenStream;
-use quote::{format_ident, ToTokens};
+use quote::{format_ident, quote, ToTokens};
 use syn::{
     self,
     parse::{Parse, ParseStream},
-    parse_quote, Data, DataEnum, DataUnion, DeriveInput, Error, Expr, Fields, Ident, Lit, Path,
-    Token, Type, Visibility,
+    Data, DataEnum, DataUnion, DeriveInput, Error, Expr, Fields, GenericArgument, Ident, Lit,
+    LitStr, Path, PathArguments, Token, Type, Visibility,
 };
 
 struct Attr<'c, T> {
@@ -49,11 +49,14 @@ pub struct Container {
     coi_path: Option<syn::Path>,
     pub providers: Vec<Provides>,
     pub injected: Vec<InjectableField>,
+    pub args: Vec<ArgField>,
+    pub dispose: Option<Dispose>,
 }
 
 impl Container {
     pub fn from_ast(cx: &Ctxt, item: &DeriveInput, is_deriving_inject: bool) -> Option<Self> {
         let mut coi_path = Attr::none(cx, CRATE);
+        let mut dispose = Attr::none(cx, DISPOSE);
         let mut providers = vec![];
 
         let coi_attrs: Vec<_> = item
@@ -79,6 +82,7 @@ impl Container {
                     providers.push(p);
                 }
                 ContainerAttr::Crate(c) => coi_path.set(attr, c.path),
+                ContainerAttr::Dispose(d) => dispose.set(attr, d),
             }
         }
 
@@ -95,6 +99,7 @@ impl Container {
         };
 
         let mut injected = vec![];
+        let mut args = vec![];
         match &data_struct.fields {
             Fields::Named(named_fields) => {
                 for field in &named_fields.named {
@@ -106,24 +111,59 @@ impl Container {
                             ));
                         }
 
-                        parse_coi_meta_items(cx, attr, field, &mut injected);
+                        parse_coi_meta_items(
+                            cx,
+                            attr,
+                            field,
+                            0,
+                            coi_path.value.as_ref(),
+                            &mut injected,
+                            &mut args,
+                        );
                     }
                 }
             }
             Fields::Unnamed(unnamed_fields) => {
-                for field in &unnamed_fields.unnamed {
+                for (index, field) in unnamed_fields.unnamed.iter().enumerate() {
                     for attr in field.attrs.iter() {
-                        parse_coi_meta_items(cx, attr, field, &mut injected);
+                        parse_coi_meta_items(
+                            cx,
+                            attr,
+                            field,
+                            index,
+                            coi_path.value.as_ref(),
+                            &mut injected,
+                            &mut args,
+                        );
                     }
                 }
             }
             Fields::Unit => {}
         };
 
+        // Arguments are only passed to `ProvideWith` impls, which are neither async nor ever
+        // cache an instance to dispose of.
+        if !args.is_empty() {
+            for provider in providers.iter().filter(|p| p.is_async) {
+                cx.push(Error::new_spanned(
+                    &provider.with,
+                    "coi field attribute arg can't be used with async providers",
+                ));
+            }
+            if dispose.value.is_some() {
+                cx.push(Error::new_spanned(
+                    &dispose.tokens,
+                    "coi attribute dispose can't be used with arg fields",
+                ));
+            }
+        }
+
         Some(Container {
             coi_path: coi_path.get(),
             providers,
             injected,
+            args,
+            dispose: dispose.get(),
         })
     }
 
@@ -151,44 +191,182 @@ fn parse_coi_meta_items(
     cx: &Ctxt,
     attr: &syn::Attribute,
     field: &syn::Field,
+    index: usize,
+    coi_path: Option<&Path>,
     injected: &mut Vec<InjectableField>,
+    args: &mut Vec<ArgField>,
 ) {
     if attr.path() != COI {
         return;
     }
 
     if let Err(e) = attr.parse_nested_meta(|meta| {
+        if meta.path == INJECT_ALL {
+            return parse_inject_all(cx, &meta, field, injected);
+        }
+
+        if meta.path == ARG {
+            // Arguments are bound the same way as fields resolved by type.
+            let name = match field.ident.as_ref().cloned() {
+                Some(ident) => ident,
+                None => format_ident!("_{}", index),
+            };
+            args.push(ArgField {
+                name,
+                ty: field.ty.clone(),
+            });
+            return Ok(());
+        }
+
         if meta.path != INJECT {
             cx.push(meta.error("unsupported attribute"));
             return Ok(());
         }
 
-        let ident: Ident = if let Ok(value) = meta.value() {
+        let (ident, key, by_type) = if let Ok(value) = meta.value() {
             let lit: Lit = value.parse()?;
+            // Keys registered by a module are qualified with its name, and are bound to the
+            // name they were registered with.
+            let (lit, key) = match lit {
+                Lit::Str(ref qualified) if qualified.value().contains("::") => {
+                    let key = qualified.value();
+                    let name = key.rsplit("::").next().unwrap_or_default();
+                    (Lit::Str(LitStr::new(name, qualified.span())), Some(key))
+                }
+                lit => (lit, None),
+            };
             let Some(ident) = get_ident_from_lit(cx, INJECT, INJECT, &lit) else {
                 return Ok(());
             };
-            ident
+            (ident, key, false)
         } else {
             match field.ident.as_ref().cloned() {
-                Some(ident) => ident,
-                None => {
-                    cx.push(Error::new_spanned(
-                        meta.path,
-                        "unnamed fields require a named injection, `#[coi(inject = \"...\")]`",
-                    ));
-                    return Ok(());
-                }
+                Some(ident) => (ident, None, false),
+                // Unnamed fields without a named injection are resolved by their type, and are
+                // bound to `_<index>` for use in the provides expr.
+                None => (format_ident!("_{}", index), None, true),
             }
         };
-        let ty = field.ty.clone();
-        injected.push(parse_quote! {#ident: #ty});
+        let (kind, ty) = field_kind(&field.ty, coi_path);
+        let injectable = match kind {
+            // `Lazy<T>` and `Factory<T>` take `T` itself rather than an `Arc<T>`.
+            FieldKind::Lazy | FieldKind::Factory => InjectableField {
+                name: ident,
+                key,
+                ty: ty.clone(),
+                by_type,
+                kind,
+            },
+            _ => {
+                let mut injectable: InjectableField = syn::parse2(quote! {#ident: #ty})?;
+                injectable.key = key;
+                injectable.by_type = by_type;
+                injectable.kind = kind;
+                injectable
+            }
+        };
+        injected.push(injectable);
         Ok(())
     }) {
         cx.push(e);
     }
 }
 
+fn parse_inject_all(
+    cx: &Ctxt,
+    meta: &syn::meta::ParseNestedMeta,
+    field: &syn::Field,
+    injected: &mut Vec<InjectableField>,
+) -> syn::Result<()> {
+    let ident = if let Ok(value) = meta.value() {
+        let lit: Lit = value.parse()?;
+        let Some(ident) = get_ident_from_lit(cx, INJECT_ALL, INJECT_ALL, &lit) else {
+            return Ok(());
+        };
+        ident
+    } else {
+        match field.ident.as_ref().cloned() {
+            Some(ident) => ident,
+            None => {
+                cx.push(meta.error("expected `#[coi(inject_all = \"<key>\")]` on unnamed fields"));
+                return Ok(());
+            }
+        }
+    };
+
+    let Some(ty) = wrapped_type(&field.ty, VEC) else {
+        cx.push(Error::new_spanned(
+            &field.ty,
+            "coi field attribute inject_all expects `Vec<Arc<...>>` type",
+        ));
+        return Ok(());
+    };
+    let mut injectable: InjectableField = syn::parse2(quote! {#ident: #ty})?;
+    injectable.kind = FieldKind::Vec;
+    injected.push(injectable);
+    Ok(())
+}
+
+/// How a field marked with `#[coi(inject)]` is resolved, based on its type, along with the type
+/// that's resolved. Wrappers are only recognized when they're written unqualified or through
+/// the path of the crate they come from, e.g. `Lazy<T>` or `coi::Lazy<T>` but not
+/// `cache::Lazy<T>`, so that unrelated types with the same name are resolved as `Arc`s.
+fn field_kind<'a>(ty: &'a Type, coi_path: Option<&Path>) -> (FieldKind, &'a Type) {
+    let coi: Vec<_> = coi_path
+        .map(|path| path.segments.iter().map(|s| s.ident.to_string()).collect())
+        .unwrap_or_else(|| vec![COI.to_string()]);
+    let coi: Vec<_> = coi.iter().map(String::as_str).collect();
+    let kinds: [(Symbol, FieldKind, &[&[&str]]); 3] = [
+        (
+            OPTION,
+            FieldKind::Option,
+            &[&[], &["std", "option"], &["core", "option"]],
+        ),
+        (LAZY, FieldKind::Lazy, &[&[], &coi]),
+        (FACTORY, FieldKind::Factory, &[&[], &coi]),
+    ];
+    let Type::Path(path) = ty else {
+        return (FieldKind::Arc, ty);
+    };
+    let segments = &path.path.segments;
+    let prefix: Vec<_> = segments
+        .iter()
+        .take(segments.len().saturating_sub(1))
+        .map(|s| s.ident.to_string())
+        .collect();
+    kinds
+        .iter()
+        .filter(|(_, _, prefixes)| {
+            prefixes.iter().any(|expected| {
+                expected
+                    .iter()
+                    .copied()
+                    .eq(prefix.iter().map(String::as_str))
+            })
+        })
+        .find_map(|&(wrapper, kind, _)| wrapped_type(ty, wrapper).map(|ty| (kind, ty)))
+        .unwrap_or((FieldKind::Arc, ty))
+}
+
+/// The `T` in `<wrapper><T>`, if `ty` is a `<wrapper>`, e.g. `Vec<T>` or `Option<T>`.
+fn wrapped_type(ty: &Type, wrapper: Symbol) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 pub fn get_ident_from_lit(
     cx: &Ctxt,
     attr_name: Symbol,
@@ -213,10 +391,20 @@ pub fn get_ident_from_lit(
 enum ContainerAttr {
     Provides(Provides),
     Crate(Crate),
+    Dispose(Dispose),
 }
 
 impl Parse for ContainerAttr {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        // Peeking for an ident matches any ident, so `dispose` has to be checked for explicitly.
+        if input
+            .fork()
+            .parse::<Ident>()
+            .is_ok_and(|ident| ident == DISPOSE)
+        {
+            return <Dispose as Parse>::parse(input).map(ContainerAttr::Dispose);
+        }
+
         let lookahead = input.lookahead1();
         if lookahead.peek(|_| PROVIDES.as_ident()) {
             <Provides as Parse>::parse(input).map(ContainerAttr::Provides)
@@ -225,7 +413,7 @@ impl Parse for ContainerAttr {
         } else {
             Err(Error::new(
                 input.span(),
-                "expected one of `crate` or `provides`",
+                "expected one of `crate`, `dispose` or `provides`",
             ))
         }
     }
@@ -250,11 +438,35 @@ impl Parse for Crate {
     }
 }
 
+pub struct Dispose {
+    pub is_async: bool,
+}
+
+impl Parse for Dispose {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse().and_then(|ident: Ident| {
+            if ident == DISPOSE {
+                Ok(())
+            } else {
+                Err(Error::new(ident.span(), "expected `dispose`"))
+            }
+        })?;
+
+        let is_async = input.peek(Token![async]);
+        if is_async {
+            let _async: Token![async] = input.parse()?;
+        }
+
+        Ok(Dispose { is_async })
+    }
+}
+
 pub struct Provides {
     pub vis: Visibility,
     pub ty: Type,
     pub with: Expr,
     pub name: Option<Ident>,
+    pub is_async: bool,
 }
 
 impl Provides {
@@ -294,6 +506,12 @@ impl Parse for Provides {
                 Err(Error::new(ident.span(), "expected `with`"))
             }
         })?;
+
+        let is_async = input.peek(Token![async]);
+        if is_async {
+            let _async: Token![async] = input.parse()?;
+        }
+
         // FIXME(pfaria) we need to limit the kinds of exprs allowed here. Quite a few will
         // fail to compile
         let with = input.parse()?;
@@ -302,13 +520,49 @@ impl Parse for Provides {
             ty,
             with,
             name,
+            is_async,
         })
     }
 }
 
+/// The type of an injected field, which decides how it's resolved.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    /// `Arc<T>`, resolved with `resolve`.
+    Arc,
+    /// `Option<Arc<T>>`, resolved with `try_resolve`.
+    Option,
+    /// `Vec<Arc<T>>`, resolved with `resolve_all`.
+    Vec,
+    /// `Lazy<T>`, created with `lazy`.
+    Lazy,
+    /// `Factory<T>`, created with `factory`.
+    Factory,
+}
+
+/// A field marked with `#[coi(arg)]`, passed to the provider by the caller rather than resolved.
+pub struct ArgField {
+    pub name: Ident,
+    pub ty: Type,
+}
+
 pub struct InjectableField {
     pub name: Ident,
+    /// The key to resolve, when it isn't just `name`.
+    pub key: Option<String>,
     pub ty: Type,
+    pub by_type: bool,
+    pub kind: FieldKind,
+}
+
+impl InjectableField {
+    /// The key this field is resolved with.
+    pub fn key(&self) -> String {
+        match &self.key {
+            Some(key) => key.clone(),
+            None => self.name.to_string(),
+        }
+    }
 }
 
 impl Parse for InjectableField {
@@ -326,6 +580,12 @@ impl Parse for InjectableField {
         let _left_angle: Token![<] = input.parse()?;
         let ty = input.parse()?;
         let _right_angle: Token![>] = input.parse()?;
-        Ok(InjectableField { name, ty })
+        Ok(InjectableField {
+            name,
+            key: None,
+            ty,
+            by_type: false,
+            kind: FieldKind::Arc,
+        })
     }
 }
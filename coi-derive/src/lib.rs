@@ -6,13 +6,13 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, DeriveInput, Error};
+use syn::{parse_macro_input, DeriveInput, Error, Ident};
 
 mod attr;
 mod ctxt;
 mod symbol;
 
-use crate::attr::Container;
+use crate::attr::{Container, FieldKind, InjectableField};
 use crate::ctxt::Ctxt;
 
 /// Generates an impl for `Inject` and also generates a "Provider" struct with its own
@@ -39,15 +39,68 @@ use crate::ctxt::Ctxt;
 /// next bullet item). `<vis>` must match the visibility of `<ty>` or you will get code that might
 /// not compile. If `<name>` is not provided, the struct name will be used and `Provider` will be
 /// appended to it.
+///
+/// Either form can also be written with `with async <expr>`, in which case `<expr>` is awaited
+/// and the provider struct impls `AsyncProvide` instead. Its `#[coi(inject)]` fields are resolved
+/// through `Container::resolve_async`, and it must itself be resolved through that fn.
 /// - `#[coi(inject)]` - All fields marked `#[coi(inject)]` are resolved in the `provide` fn
-/// described above.
-/// Given a field `<field_name>: <field_ty>`, this attribute will cause the following resolution to
-/// be generated:
+///   described above.
+///   Given a field `<field_name>: <field_ty>`, this attribute will cause the following resolution to
+///   be generated:
 /// ```rust,ignore
 /// let <field_name> = Container::resolve::<<field_ty>>(container, "<field_name>");
 /// ```
 /// Because of this, it's important that the field name *must* match the string that's used to
-/// register the provider in the `ContainerBuilder`.
+/// register the provider in the `ContainerBuilder`. If it doesn't, the returned
+/// `Error::KeyNotFound` contains the field's type and the chain of keys that lead to it.
+///
+/// Unnamed fields can either be given a key with `#[coi(inject = "<key>")]`, or be marked with
+/// just `#[coi(inject)]`, in which case they are resolved by type with
+/// `Container::resolve_type::<<field_ty>>()` and can be referred to as `_<index>` in `<expr>`.
+/// Dependencies resolved by type aren't listed by the generated `Provide::dependencies`, which
+/// only holds string keys, so `Container::analyze` doesn't report them when they're missing and
+/// `Container::with_overrides` doesn't follow them.
+///
+/// Keys exported by a module are given as `#[coi(inject = "<module>::<key>")]`, and the field is
+/// referred to as `<key>` in `<expr>`.
+///
+/// Fields of type `Option<Arc<<ty>>>` are optional dependencies. They're resolved with
+/// `Container::try_resolve`, so they're `None` when nothing is registered for them instead of
+/// failing construction.
+///
+/// Fields of type `Lazy<<ty>>` or `Factory<<ty>>` aren't resolved while constructing the struct.
+/// They're created with `Container::lazy` or `Container::factory` instead, and resolve `<ty>`
+/// on the first call to `Lazy::get` or on every call to `Factory::create`, respectively. Both
+/// only hold a weak reference to the container that constructed the struct, so they fail with
+/// `Error::ContainerDropped` once it's dropped: a struct resolved from a temporary scope, as in
+/// `container.scoped().resolve(..)`, can't use them after that statement ends.
+///
+/// `Option`, `Lazy` and `Factory` are recognized by their path, so they must be written either
+/// unqualified or fully qualified, e.g. `coi::Lazy<<ty>>` or `std::option::Option<<ty>>`. Types
+/// with the same name from other crates, like `once_cell::sync::Lazy<<ty>>`, aren't treated
+/// specially.
+/// - `#[coi(inject_all = "<key>")]` - Fields of type `Vec<Arc<<ty>>>` marked with this attribute
+///   are resolved with `Container::resolve_all::<<ty>>("<key>")`, i.e. they get every provider
+///   registered under `<key>` through `ContainerBuilder::register_all`. The key defaults to the
+///   field name when it's omitted, and the field is referred to as `<key>` in `<expr>`.
+/// - `#[coi(arg)]` - Fields marked with this attribute are supplied by the caller of
+///   `Container::resolve_with` rather than resolved, and are referred to by their name in
+///   `<expr>`, or as `_<index>` for unnamed fields. The generated provider impls
+///   `ProvideWith<<args>>` instead of `Provide`, where `<args>` is the type of the only such
+///   field, or a tuple of their types in field order. It's registered with
+///   `ContainerBuilder::register_with`, and can't be async or combined with `#[coi(dispose)]`.
+///
+///   A typed factory is generated alongside each such provider, named after it with `Provider`
+///   replaced by `Factory`, or with `Factory` appended. It's created with the key the provider
+///   was registered with, and its `create` takes the container followed by each argument in
+///   field order:
+/// ```rust,ignore
+/// let export = ExportFactory::new("export").create(&container, user_id, path)?;
+/// ```
+/// - `#[coi(dispose)]` - The generated providers return `Disposer::Sync(<ty>::dispose)` from
+///   `disposer`, so scoped and singleton instances are disposed of through `coi::Dispose` when
+///   their container is. `#[coi(dispose async)]` uses `coi::AsyncDispose` instead. `<ty>` must
+///   impl the corresponding trait, which for `dyn Trait`s means having it as a supertrait.
 ///
 /// ## Examples
 ///
@@ -118,6 +171,57 @@ use crate::ctxt::Ctxt;
 /// struct Impl1(#[coi(inject = "dep1")] Arc<Dep1>);
 /// ```
 ///
+/// Type keyed injection
+/// ```rust
+/// use coi::{ContainerBuilder, Inject};
+/// # use coi_derive::Inject;
+/// use std::sync::Arc;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Dep1 with Dep1)]
+/// struct Dep1;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Impl1 with Impl1(_0))]
+/// struct Impl1(#[coi(inject)] Arc<Dep1>);
+///
+/// let container = ContainerBuilder::new()
+///     .register_type::<Dep1, _>(Dep1Provider)
+///     .register_type::<Impl1, _>(Impl1Provider)
+///     .build();
+/// let impl1 = container.resolve_type::<Impl1>().expect("Should exist");
+/// ```
+///
+/// Async construction
+/// ```rust
+/// use coi::{ContainerBuilder, Inject};
+/// # use coi_derive::Inject;
+/// use std::sync::Arc;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Config with Config)]
+/// struct Config;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Pool with async Pool::connect(config))]
+/// struct Pool {
+///     #[coi(inject)]
+///     config: Arc<Config>,
+/// }
+///
+/// impl Pool {
+///     async fn connect(config: Arc<Config>) -> Self {
+///         Self { config }
+///     }
+/// }
+///
+/// let container = ContainerBuilder::new()
+///     .register("config", ConfigProvider)
+///     .register_async("pool", PoolProvider)
+///     .build();
+/// let pool = container.resolve_async::<Pool>("pool");
+/// ```
+///
 /// Generics
 /// ```rust
 /// use coi::{container, Inject};
@@ -153,9 +257,40 @@ use crate::ctxt::Ctxt;
 /// # build_container();
 /// ```
 ///
-/// If you need some form of constructor fn that takes arguments that are not injected, then you
-/// might be able to use the [`coi::Provide`] derive. If that doesn't fit your use case, you'll
-/// need to manually implement `Provide`.
+/// Runtime arguments
+/// ```rust
+/// use coi::{ContainerBuilder, Inject};
+/// # use coi_derive::Inject;
+/// use std::sync::Arc;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Db with Db)]
+/// struct Db;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Export with Export { db, user_id, path })]
+/// struct Export {
+///     #[coi(inject)]
+///     db: Arc<Db>,
+///     #[coi(arg)]
+///     user_id: u64,
+///     #[coi(arg)]
+///     path: String,
+/// }
+///
+/// let container = ContainerBuilder::new()
+///     .register("db", DbProvider)
+///     .register_with("export", ExportProvider)
+///     .build();
+/// let export = container
+///     .resolve_with::<Export, (u64, String)>("export", (42, "out.csv".to_owned()))
+///     .expect("Should exist");
+/// assert_eq!(export.user_id, 42);
+/// ```
+///
+/// If you need some form of constructor fn that takes arguments that are not injected, then
+/// `#[coi(arg)]` fields or the [`coi::Provide`] derive might fit. If neither fits your use case,
+/// you'll need to manually implement `Provide` or `ProvideWith`.
 ///
 /// [`coi::Provide`]: derive.Provide.html
 #[proc_macro_derive(Inject, attributes(coi))]
@@ -195,41 +330,53 @@ pub fn inject_derive(input: TokenStream) -> TokenStream {
         .into();
     }
 
+    // Named so it can't clash with the fields bound next to it.
     let container_ident = format_ident!(
         "{}",
         if container.injected.is_empty() {
             "_"
         } else {
-            "container"
+            "__coi_container"
         }
     );
-    let (resolve, keys): (Vec<_>, Vec<_>) = container
+    let resolve: Vec<_> = container
         .injected
-        .into_iter()
-        .map(|field| {
-            let ident = field.name;
-            let ty = field.ty;
-            let key = format!("{}", ident);
-            (
-                quote! {
-                    let #ident = #container_ident.resolve::<#ty>(#key)?;
-                },
-                key,
-            )
-        })
-        .unzip();
+        .iter()
+        .map(|field| resolve_field(field, &container_ident, false))
+        .collect();
+    let resolve_async: Vec<_> = container
+        .injected
+        .iter()
+        .map(|field| resolve_field(field, &container_ident, true))
+        .collect();
+    // TODO: type keyed dependencies can't be listed here until `type_name` is usable in const
+    // contexts.
+    let keys: Vec<_> = container
+        .injected
+        .iter()
+        .filter(|field| !field.by_type && field.kind != FieldKind::Option)
+        .map(|field| field.key())
+        .collect();
+    let optional_keys: Vec<_> = container
+        .injected
+        .iter()
+        .filter(|field| !field.by_type && field.kind == FieldKind::Option)
+        .map(|field| field.key())
+        .collect();
     let input_ident = input.ident;
 
-    let dependencies_fn = if cfg!(feature = "debug") {
-        vec![quote! {
-            fn dependencies(&self) -> &'static[&'static str] {
-                &[
-                    #( #keys, )*
-                ]
-            }
-        }]
-    } else {
-        vec![]
+    let dependencies_fn = quote! {
+        fn dependencies(&self) -> ::std::option::Option<&'static[&'static str]> {
+            ::std::option::Option::Some(&[
+                #( #keys, )*
+            ])
+        }
+
+        fn optional_dependencies(&self) -> &'static[&'static str] {
+            &[
+                #( #optional_keys, )*
+            ]
+        }
     };
 
     let provider_fields = if has_generics {
@@ -268,6 +415,26 @@ pub fn inject_derive(input: TokenStream) -> TokenStream {
         vec![]
     };
 
+    let dispose = container.dispose;
+    // Providers of structs with `#[coi(arg)]` fields take them as a single argument, or as a
+    // tuple in field order if there's more than one.
+    let (args_pattern, args_ty) = match container.args.as_slice() {
+        [] => (None, None),
+        [arg] => {
+            let (name, ty) = (&arg.name, &arg.ty);
+            (Some(quote! { #name }), Some(quote! { #ty }))
+        }
+        args => {
+            let names = args.iter().map(|arg| &arg.name);
+            let tys = args.iter().map(|arg| &arg.ty);
+            (
+                Some(quote! { ( #( #names ),* ) }),
+                Some(quote! { ( #( #tys ),* ) }),
+            )
+        }
+    };
+    let arg_names: Vec<_> = container.args.iter().map(|arg| arg.name.clone()).collect();
+    let arg_tys: Vec<_> = container.args.iter().map(|arg| arg.ty.clone()).collect();
     let constructed_provides: Vec<_> = container
         .providers
         .into_iter()
@@ -276,6 +443,98 @@ pub fn inject_derive(input: TokenStream) -> TokenStream {
             let vis = p.vis;
             let ty = p.ty;
             let provides_with = p.with;
+            let disposer_fn = dispose.as_ref().map(|dispose| {
+                let disposer = if dispose.is_async {
+                    quote! { #coi::Disposer::Async(<#ty as #coi::AsyncDispose>::dispose) }
+                } else {
+                    quote! { #coi::Disposer::Sync(<#ty as #coi::Dispose>::dispose) }
+                };
+                quote! {
+                    fn disposer(&self) -> ::std::option::Option<#coi::Disposer<Self::Output>> {
+                        ::std::option::Option::Some(#disposer)
+                    }
+                }
+            });
+
+            if p.is_async {
+                return quote! {
+                    #vis struct #provider #generics #provider_fields #where_clause;
+
+                    impl #generics #coi::AsyncProvide for #provider #generics #where_clause {
+                        type Output = #ty;
+
+                        fn provide<'coi>(
+                            &'coi self,
+                            #container_ident: &'coi #coi::Container,
+                        ) -> #coi::BoxFuture<'coi, #coi::Result<::std::sync::Arc<Self::Output>>> {
+                            ::std::boxed::Box::pin(async move {
+                                #( #resolve_async )*
+                                Ok(::std::sync::Arc::new((#provides_with).await) as ::std::sync::Arc<#ty>)
+                            })
+                        }
+
+                        #dependencies_fn
+
+                        #disposer_fn
+                    }
+                };
+            }
+
+            if let (Some(args_pattern), Some(args_ty)) = (&args_pattern, &args_ty) {
+                // `FooProvider` gets a `FooFactory` that takes each argument separately.
+                let factory = match provider.to_string().strip_suffix("Provider") {
+                    Some(base) if !base.is_empty() => format_ident!("{}Factory", base),
+                    _ => format_ident!("{}Factory", provider),
+                };
+                return quote! {
+                    #vis struct #provider #generics #provider_fields #where_clause;
+
+                    #vis struct #factory #generics #where_clause {
+                        key: &'static str,
+                        _provider: ::std::marker::PhantomData<fn() -> #provider #generics>,
+                    }
+
+                    impl #generics #factory #generics #where_clause {
+                        #vis fn new(key: &'static str) -> Self {
+                            Self {
+                                key,
+                                _provider: ::std::marker::PhantomData,
+                            }
+                        }
+
+                        #vis fn create(
+                            &self,
+                            __coi_container: &#coi::Container,
+                            #( #arg_names: #arg_tys ),*
+                        ) -> #coi::Result<::std::sync::Arc<#ty>> {
+                            __coi_container.resolve_with::<#ty, #args_ty>(self.key, #args_pattern)
+                        }
+                    }
+
+                    impl #generics ::std::clone::Clone for #factory #generics #where_clause {
+                        fn clone(&self) -> Self {
+                            *self
+                        }
+                    }
+
+                    impl #generics ::std::marker::Copy for #factory #generics #where_clause {}
+
+                    impl #generics #coi::ProvideWith<#args_ty> for #provider #generics #where_clause {
+                        type Output = #ty;
+
+                        fn provide_with(
+                            &self,
+                            #container_ident: &#coi::Container,
+                            #args_pattern: #args_ty,
+                        ) -> #coi::Result<::std::sync::Arc<Self::Output>> {
+                            #( #resolve )*
+                            Ok(::std::sync::Arc::new(#provides_with) as ::std::sync::Arc<#ty>)
+                        }
+
+                        #dependencies_fn
+                    }
+                };
+            }
 
             quote! {
                 #vis struct #provider #generics #provider_fields #where_clause;
@@ -291,7 +550,9 @@ pub fn inject_derive(input: TokenStream) -> TokenStream {
                         Ok(::std::sync::Arc::new(#provides_with) as ::std::sync::Arc<#ty>)
                     }
 
-                    #( #dependencies_fn )*
+                    #dependencies_fn
+
+                    #disposer_fn
                 }
             }
         })
@@ -389,18 +650,10 @@ pub fn provide_derive(input: TokenStream) -> TokenStream {
         })
         .unwrap_or_default();
 
-    let dependencies_fn = if cfg!(feature = "debug") {
-        vec![{
-            quote! {
-                fn dependencies(
-                    &self
-                ) -> &'static [&'static str] {
-                    &[]
-                }
-            }
-        }]
-    } else {
-        vec![]
+    let dependencies_fn = quote! {
+        fn dependencies(&self) -> ::std::option::Option<&'static [&'static str]> {
+            ::std::option::Option::Some(&[])
+        }
     };
 
     let coi = container.coi_path();
@@ -421,7 +674,7 @@ pub fn provide_derive(input: TokenStream) -> TokenStream {
                         Ok(::std::sync::Arc::new(#provides_with) as ::std::sync::Arc<#ty>)
                     }
 
-                    #( #dependencies_fn )*
+                    #dependencies_fn
                 }
             }
         })
@@ -435,3 +688,54 @@ fn to_compile_errors(errors: Vec<Error>) -> proc_macro2::TokenStream {
     let compile_errors = errors.iter().map(Error::to_compile_error);
     quote!(#(#compile_errors)*)
 }
+
+/// The statement that resolves `field` from the container bound to `container_ident`, in an
+/// `AsyncProvide` impl if `is_async` is set.
+fn resolve_field(
+    field: &InjectableField,
+    container_ident: &Ident,
+    is_async: bool,
+) -> proc_macro2::TokenStream {
+    let ident = &field.name;
+    let ty = &field.ty;
+    let key = field.key();
+    let (name, args) = if field.by_type {
+        (String::from("_type"), quote! {})
+    } else {
+        (String::new(), quote! { #key })
+    };
+    // Collections, lazies and factories are only ever resolved synchronously, and the latter two
+    // don't resolve anything until they're used.
+    let resolve = match field.kind {
+        FieldKind::Vec => {
+            return quote! {
+                let #ident = #container_ident.resolve_all::<#ty>(#key)?;
+            }
+        }
+        FieldKind::Lazy => {
+            let lazy = format_ident!("lazy{}", name);
+            return quote! {
+                let #ident = #container_ident.#lazy::<#ty>(#args);
+            };
+        }
+        FieldKind::Factory => {
+            let factory = format_ident!("factory{}", name);
+            return quote! {
+                let #ident = #container_ident.#factory::<#ty>(#args);
+            };
+        }
+        FieldKind::Arc => format!("resolve{}", name),
+        FieldKind::Option => format!("try_resolve{}", name),
+    };
+    if is_async {
+        let resolve = format_ident!("{}_async", resolve);
+        quote! {
+            let #ident = #container_ident.#resolve::<#ty>(#args).await?;
+        }
+    } else {
+        let resolve = format_ident!("{}", resolve);
+        quote! {
+            let #ident = #container_ident.#resolve::<#ty>(#args)?;
+        }
+    }
+}
@@ -6,10 +6,17 @@ use syn::{Ident, Path};
 pub struct Symbol(&'static str);
 
 pub const ARC: Symbol = Symbol("Arc");
+pub const ARG: Symbol = Symbol("arg");
 pub const COI: Symbol = Symbol("coi");
 pub const CRATE: Symbol = Symbol("crate");
+pub const DISPOSE: Symbol = Symbol("dispose");
+pub const FACTORY: Symbol = Symbol("Factory");
 pub const INJECT: Symbol = Symbol("inject");
+pub const INJECT_ALL: Symbol = Symbol("inject_all");
+pub const LAZY: Symbol = Symbol("Lazy");
+pub const OPTION: Symbol = Symbol("Option");
 pub const PROVIDES: Symbol = Symbol("provides");
+pub const VEC: Symbol = Symbol("Vec");
 
 impl PartialEq<Symbol> for Ident {
     fn eq(&self, sym: &Symbol) -> bool {
@@ -17,7 +24,7 @@ impl PartialEq<Symbol> for Ident {
     }
 }
 
-impl<'a> PartialEq<Symbol> for &'a Ident {
+impl PartialEq<Symbol> for &Ident {
     fn eq(&self, sym: &Symbol) -> bool {
         *self == sym.0
     }
@@ -29,7 +36,7 @@ impl PartialEq<Symbol> for Path {
     }
 }
 
-impl<'a> PartialEq<Symbol> for &'a Path {
+impl PartialEq<Symbol> for &Path {
     fn eq(&self, sym: &Symbol) -> bool {
         self.is_ident(sym.0)
     }
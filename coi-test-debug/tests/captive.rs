@@ -0,0 +1,76 @@
+use coi::{container, AnalysisError, Inject};
+use std::sync::Arc;
+
+#[derive(Inject)]
+#[coi(provides Request with Request)]
+struct Request;
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Session with Session { request })]
+struct Session {
+    #[coi(inject)]
+    request: Arc<Request>,
+}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Cache with Cache { session })]
+struct Cache {
+    #[coi(inject)]
+    session: Arc<Session>,
+}
+
+fn captive(errors: &[AnalysisError]) -> Vec<(&'static str, &'static str)> {
+    errors
+        .iter()
+        .filter_map(|e| match e {
+            AnalysisError::CaptiveDependency {
+                consumer,
+                dependency,
+            } => Some((*consumer, *dependency)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn singleton_depending_on_scoped_is_captive() {
+    let container = container! {
+        request => RequestProvider; scoped,
+        session => SessionProvider; singleton,
+    };
+
+    let errors = container.analyze().unwrap_err();
+    assert_eq!(captive(&errors), [("session", "request")]);
+}
+
+#[test]
+fn singleton_depending_on_scoped_through_transient_is_captive() {
+    let container = container! {
+        request => RequestProvider; scoped,
+        session => SessionProvider; transient,
+        cache => CacheProvider; singleton,
+    };
+
+    let errors = container.analyze().unwrap_err();
+    assert_eq!(captive(&errors), [("cache", "request")]);
+}
+
+#[test]
+fn scoped_and_singleton_dependencies_are_not_captive() {
+    let container = container! {
+        request => RequestProvider; singleton,
+        session => SessionProvider; scoped,
+        cache => CacheProvider; scoped,
+    };
+    assert!(container.analyze().is_ok());
+
+    // A singleton's scoped dependencies are only reached through other scoped ones here.
+    let container = container! {
+        request => RequestProvider; scoped,
+        session => SessionProvider; scoped,
+        cache => CacheProvider; transient,
+    };
+    assert!(container.analyze().is_ok());
+}
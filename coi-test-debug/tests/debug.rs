@@ -89,3 +89,51 @@ fn main() {
     assert!(debugged.contains(r#""trait5": ["trait1", "trait2", "trait3", "trait4"]"#));
     assert!(debugged.contains(r#""structa": []"#));
 }
+
+#[test]
+fn instances_are_drawn_as_their_own_kind() {
+    let container = container! {
+        trait1 => instance(Arc::new(Impl1) as Arc<dyn Trait1>),
+        trait2 => Impl2Provider,
+        trait3 => Impl3Provider,
+        trait4 => Impl4Provider,
+        trait5 => Impl5Provider,
+    };
+
+    assert!(container.analyze().is_ok());
+    let graph = container.dot_graph();
+    assert!(graph.contains("Instance - trait1"));
+    assert!(graph.contains("shape = box"));
+    assert!(graph.contains("Transient - trait2"));
+}
+
+struct Handwritten;
+
+impl Provide for Handwritten {
+    type Output = dyn Trait1;
+
+    fn provide(&self, container: &coi::Container) -> coi::Result<Arc<dyn Trait1>> {
+        container.resolve::<dyn Trait2>("trait2")?;
+        Ok(Arc::new(Impl1))
+    }
+}
+
+#[test]
+fn providers_without_dependencies_still_build() {
+    let container = container! {
+        trait1 => Handwritten,
+        trait2 => |_: &coi::Container| Ok(Arc::new(Impl2) as Arc<dyn Trait2>),
+        trait3 => coi::provide_closure!(|trait2: Arc<dyn Trait2>| {
+            let _ = trait2;
+            Ok(Arc::new(Impl3) as Arc<dyn Trait3>)
+        }),
+    };
+
+    assert!(container.resolve::<dyn Trait1>("trait1").is_ok());
+    assert!(container.resolve::<dyn Trait3>("trait3").is_ok());
+    assert!(container.analyze().is_ok());
+    let debugged = format!("{:?}", container);
+    assert!(debugged.contains(r#""trait1": unknown"#));
+    assert!(debugged.contains(r#""trait2": unknown"#));
+    assert!(debugged.contains(r#""trait3": ["trait2"]"#));
+}
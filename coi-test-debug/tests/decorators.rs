@@ -0,0 +1,68 @@
+use coi::{
+    container, AnalysisError, Container, ContainerBuilder, Inject, Registration, RegistrationKind,
+};
+use std::sync::Arc;
+
+#[derive(Inject)]
+#[coi(provides Request with Request)]
+struct Request;
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Handler with Handler { request })]
+struct Handler {
+    #[coi(inject)]
+    request: Arc<Request>,
+}
+
+fn passthrough(inner: Arc<Request>, _: &Container) -> coi::Result<Arc<Request>> {
+    Ok(inner)
+}
+
+#[test]
+fn decorators_are_drawn_as_their_own_nodes() {
+    let container = container! {
+        request => RequestProvider,
+        handler => HandlerProvider,
+    };
+    let container = container.with_overrides(|builder| {
+        builder
+            .register("request", RequestProvider)
+            .decorate("request", passthrough)
+            .unwrap()
+            .decorate("request", passthrough)
+            .unwrap()
+    });
+
+    assert!(container.analyze().is_ok());
+    let graph = container.dot_graph();
+    assert!(graph.contains("Transient - request"));
+    assert!(graph.contains("Decorator 1 - request"));
+    assert!(graph.contains("Decorator 2 - request"));
+    assert!(graph.contains("style = dashed"));
+}
+
+#[test]
+fn decorated_scoped_dependencies_are_still_captive() {
+    let container = ContainerBuilder::new()
+        .register_as(
+            "request",
+            Registration::new(RegistrationKind::Scoped, RequestProvider),
+        )
+        .register_as(
+            "handler",
+            Registration::new(RegistrationKind::Singleton, HandlerProvider),
+        )
+        .decorate("request", passthrough)
+        .unwrap()
+        .build();
+
+    let errors = container.analyze().unwrap_err();
+    assert!(errors.iter().any(|e| matches!(
+        e,
+        AnalysisError::CaptiveDependency {
+            consumer: "handler",
+            dependency: "request",
+        }
+    )));
+}
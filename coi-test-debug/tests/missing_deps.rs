@@ -54,3 +54,123 @@ fn validate_missing() {
         _ => false,
     }));
 }
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn Trait3 with Impl3::new(handlers))]
+struct Impl3 {
+    #[coi(inject_all)]
+    handlers: Vec<Arc<dyn Trait1>>,
+}
+
+impl Trait3 for Impl3 {}
+impl Impl3 {
+    fn new(handlers: Vec<Arc<dyn Trait1>>) -> Self {
+        Self { handlers }
+    }
+}
+
+#[test]
+fn validate_missing_in_collection() {
+    let container = coi::ContainerBuilder::new()
+        .register_all("handlers", Impl1Provider)
+        .build();
+
+    let errors = container.analyze().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(match &errors[0] {
+        AnalysisError::Missing(from, to) => *from == "handlers" && *to == "t3",
+        _ => false,
+    });
+}
+
+#[test]
+fn collection_satisfies_dependency() {
+    let container = coi::ContainerBuilder::new()
+        .register("t3", Impl3Provider)
+        .register_all("handlers", Impl1Provider)
+        .build();
+
+    // t3 -> handlers -> t3
+    let errors = container.analyze().unwrap_err();
+    assert!(errors.iter().all(|e| matches!(e, AnalysisError::Cycle(_))));
+    assert!(!errors.is_empty());
+}
+
+trait Metrics: Inject {}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn Trait2 with Impl4 { metrics, t1 })]
+struct Impl4 {
+    #[coi(inject)]
+    metrics: Option<Arc<dyn Metrics>>,
+    #[coi(inject)]
+    t1: Option<Arc<dyn Trait1>>,
+}
+
+impl Trait2 for Impl4 {}
+
+#[test]
+fn optional_dependencies_are_not_missing() {
+    let container = container! {
+        t2 => Impl4Provider,
+    };
+
+    assert!(container.analyze().is_ok());
+    assert!(container.dot_graph().contains("style = dashed"));
+}
+
+struct AppModule;
+
+impl coi::Module for AppModule {
+    fn name(&self) -> &'static str {
+        "m"
+    }
+
+    fn exports(&self) -> &'static [&'static str] {
+        &["t1"]
+    }
+
+    fn register(&self, builder: coi::ContainerBuilder) -> coi::ContainerBuilder {
+        builder
+            .register("t1", Impl1Provider)
+            .register("t3", Impl5Provider)
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Trait3 with Impl5)]
+struct Impl5;
+
+impl Trait3 for Impl5 {}
+
+#[test]
+fn module_dependencies_refer_to_their_own_module() {
+    let container = coi::ContainerBuilder::new()
+        .add_module(AppModule)
+        .register("t2", Impl2Provider)
+        .build();
+
+    let errors = container.analyze().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], AnalysisError::Missing("t2", "t3")));
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Trait1 with Leaf)]
+struct Leaf;
+
+impl Trait1 for Leaf {}
+
+#[test]
+fn collections_are_distinct_from_keys_with_the_same_name() {
+    // The items of the `t3` collection depend on the plain `t3` key, which isn't a cycle.
+    let container = coi::ContainerBuilder::new()
+        .register("t3", Impl3Provider)
+        .register_all("t3", Impl1Provider)
+        .register_all("handlers", LeafProvider)
+        .build();
+
+    assert!(container.analyze().is_ok());
+}
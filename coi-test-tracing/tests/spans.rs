@@ -0,0 +1,217 @@
+use coi::{container, Container, ContainerBuilder, Inject, Registration, RegistrationKind};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_core::span::Current;
+
+/// A span recorded by `Recorder`, with its fields formatted.
+#[derive(Clone, Debug)]
+struct RecordedSpan {
+    parent: Option<usize>,
+    fields: HashMap<&'static str, String>,
+    metadata: &'static Metadata<'static>,
+}
+
+impl RecordedSpan {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+struct Fields<'a>(&'a mut HashMap<&'static str, String>);
+
+impl Visit for Fields<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+/// Records every span along with the span it was created in. Only used from a single thread.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    entered: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Recorder {
+    fn spans(&self) -> Vec<RecordedSpan> {
+        self.spans.lock().unwrap().clone()
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let parent = match attrs.parent() {
+            Some(parent) => Some(parent.into_u64() as usize - 1),
+            None if attrs.is_contextual() => self.entered.lock().unwrap().last().copied(),
+            None => None,
+        };
+        let mut fields = HashMap::new();
+        attrs.record(&mut Fields(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(RecordedSpan {
+            parent,
+            fields,
+            metadata: attrs.metadata(),
+        });
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        let span = &mut spans[span.into_u64() as usize - 1];
+        values.record(&mut Fields(&mut span.fields));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.entered
+            .lock()
+            .unwrap()
+            .push(span.into_u64() as usize - 1);
+    }
+
+    fn exit(&self, _: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> Current {
+        match self.entered.lock().unwrap().last() {
+            Some(&index) => Current::new(
+                Id::from_u64(index as u64 + 1),
+                self.spans.lock().unwrap()[index].metadata,
+            ),
+            None => Current::none(),
+        }
+    }
+}
+
+/// The spans recorded while running `f`.
+fn record<F: FnOnce()>(f: F) -> Vec<RecordedSpan> {
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), f);
+    recorder.spans()
+}
+
+#[derive(Inject)]
+#[coi(provides Config with Config)]
+struct Config;
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Server with Server { config })]
+struct Server {
+    #[coi(inject)]
+    config: Arc<Config>,
+}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Pool with async Pool::connect(config))]
+struct Pool {
+    #[coi(inject)]
+    config: Arc<Config>,
+}
+
+impl Pool {
+    async fn connect(config: Arc<Config>) -> Self {
+        Pool { config }
+    }
+}
+
+fn container() -> Container {
+    ContainerBuilder::new()
+        .register_as(
+            "config",
+            Registration::new(RegistrationKind::Singleton, ConfigProvider),
+        )
+        .register("server", ServerProvider)
+        .register_async("pool", PoolProvider)
+        .build()
+}
+
+#[test]
+fn spans_are_nested_by_dependency() {
+    let container = container();
+    let spans = record(|| {
+        container.resolve::<Server>("server").unwrap();
+    });
+
+    assert_eq!(spans.len(), 2);
+    let (server, config) = (&spans[0], &spans[1]);
+    assert_eq!(server.parent, None);
+    assert_eq!(server.field("key"), Some("server"));
+    assert!(server.field("type_name").unwrap().ends_with("Server"));
+    assert_eq!(server.field("kind"), Some("Transient"));
+    assert_eq!(server.field("cached"), Some("false"));
+    assert_eq!(config.parent, Some(0));
+    assert_eq!(config.field("key"), Some("config"));
+    assert_eq!(config.field("kind"), Some("Singleton"));
+    assert_eq!(config.field("cached"), Some("false"));
+}
+
+#[test]
+fn cached_instances_are_recorded() {
+    let container = container();
+    container.resolve::<Config>("config").unwrap();
+    let spans = record(|| {
+        container.scoped().resolve::<Server>("server").unwrap();
+    });
+
+    assert_eq!(spans.len(), 2);
+    assert_eq!(spans[0].field("cached"), Some("false"));
+    assert_eq!(spans[1].parent, Some(0));
+    assert_eq!(spans[1].field("cached"), Some("true"));
+}
+
+#[test]
+fn errors_are_recorded() {
+    let container = container! {
+        server => ServerProvider,
+    };
+    let spans = record(|| {
+        assert!(container.resolve::<Server>("server").is_err());
+    });
+
+    assert_eq!(spans.len(), 2);
+    let (server, config) = (&spans[0], &spans[1]);
+    assert!(server
+        .field("error")
+        .unwrap()
+        .starts_with("Key not found: config"));
+    assert_eq!(config.parent, Some(0));
+    assert_eq!(config.field("kind"), None);
+    assert!(config
+        .field("error")
+        .unwrap()
+        .starts_with("Key not found: config"));
+}
+
+#[test]
+fn async_spans_are_nested_by_dependency() {
+    let container = container();
+    let spans = record(|| {
+        block_on(container.resolve_async::<Pool>("pool")).unwrap();
+    });
+
+    assert_eq!(spans.len(), 2);
+    assert_eq!(spans[0].field("key"), Some("pool"));
+    assert_eq!(spans[0].field("cached"), Some("false"));
+    assert_eq!(spans[1].field("key"), Some("config"));
+    assert_eq!(spans[1].parent, Some(0));
+}
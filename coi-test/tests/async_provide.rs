@@ -0,0 +1,174 @@
+use coi::{
+    AsyncProvide, BoxFuture, Container, ContainerBuilder, Error, Inject, Registration,
+    RegistrationKind,
+};
+use futures::executor::block_on;
+use std::{
+    future::Future,
+    ops::Deref,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+#[derive(Inject)]
+#[coi(provides Config with Config { url: "db://" })]
+struct Config {
+    url: &'static str,
+}
+
+trait Pool: Inject {
+    fn url(&self) -> &'static str;
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Pool with async PoolImpl::connect(config))]
+struct PoolImpl {
+    #[coi(inject)]
+    config: Arc<Config>,
+}
+
+impl PoolImpl {
+    async fn connect(config: Arc<Config>) -> Self {
+        YieldOnce(false).await;
+        Self { config }
+    }
+}
+
+impl Pool for PoolImpl {
+    fn url(&self) -> &'static str {
+        self.config.url
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides Repo with async Repo::new(pool))]
+struct Repo {
+    #[coi(inject)]
+    pool: Arc<dyn Pool>,
+}
+
+impl Repo {
+    async fn new(pool: Arc<dyn Pool>) -> Self {
+        Self { pool }
+    }
+}
+
+/// Returns `Pending` the first time it's polled, so that concurrently polled futures interleave.
+struct YieldOnce(bool);
+
+impl Future for YieldOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+struct Counted;
+
+impl Inject for Counted {}
+
+struct CountingProvider(Arc<AtomicUsize>);
+
+impl AsyncProvide for CountingProvider {
+    type Output = Counted;
+
+    fn provide<'a>(&'a self, _: &'a Container) -> BoxFuture<'a, coi::Result<Arc<Counted>>> {
+        Box::pin(async move {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            YieldOnce(false).await;
+            Ok(Arc::new(Counted))
+        })
+    }
+}
+
+fn counting_container(kind: RegistrationKind) -> (Container, Arc<AtomicUsize>) {
+    let count = Arc::new(AtomicUsize::new(0));
+    let container = ContainerBuilder::new()
+        .register_async_as(
+            "counted",
+            Registration::new(kind, CountingProvider(Arc::clone(&count))),
+        )
+        .build();
+    (container, count)
+}
+
+#[test]
+fn async_providers_resolve_sync_and_async_dependencies() {
+    let container = ContainerBuilder::new()
+        .register("config", ConfigProvider)
+        .register_async("pool", PoolImplProvider)
+        .register_async("repo", RepoProvider)
+        .build();
+    let repo = block_on(container.resolve_async::<Repo>("repo")).unwrap();
+    assert_eq!(repo.pool.url(), "db://");
+
+    let config = block_on(container.resolve_async::<Config>("config")).unwrap();
+    assert_eq!(config.url, "db://");
+}
+
+#[test]
+fn sync_resolution_of_async_provider_errors() {
+    let container = ContainerBuilder::new()
+        .register("config", ConfigProvider)
+        .register_async("pool", PoolImplProvider)
+        .build();
+    match container.resolve::<dyn Pool>("pool") {
+        Err(Error::RequiresAsync(key)) => assert_eq!(key, "pool"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("async provider should not be resolved synchronously"),
+    }
+}
+
+#[test]
+fn concurrent_singleton_resolution_constructs_once() {
+    let (container, count) = counting_container(RegistrationKind::Singleton);
+    let (a, b) = block_on(futures::future::join(
+        container.resolve_async::<Counted>("counted"),
+        container.resolve_async::<Counted>("counted"),
+    ));
+    let (a, b) = (a.unwrap(), b.unwrap());
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert_eq!(a.deref() as *const _, b.deref() as *const _);
+
+    let scoped = container.scoped();
+    let c = block_on(scoped.resolve_async::<Counted>("counted")).unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert_eq!(a.deref() as *const _, c.deref() as *const _);
+}
+
+#[test]
+fn concurrent_scoped_resolution_constructs_once_per_scope() {
+    let (container, count) = counting_container(RegistrationKind::Scoped);
+    let scoped = container.scoped();
+    let (a, b, c) = block_on(futures::future::join3(
+        container.resolve_async::<Counted>("counted"),
+        scoped.resolve_async::<Counted>("counted"),
+        scoped.resolve_async::<Counted>("counted"),
+    ));
+    let (a, b, c) = (a.unwrap(), b.unwrap(), c.unwrap());
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+    assert_ne!(a.deref() as *const _, b.deref() as *const _);
+    assert_eq!(b.deref() as *const _, c.deref() as *const _);
+}
+
+#[test]
+fn transient_resolution_constructs_every_time() {
+    let (container, count) = counting_container(RegistrationKind::Transient);
+    let (a, b) = block_on(futures::future::join(
+        container.resolve_async::<Counted>("counted"),
+        container.resolve_async::<Counted>("counted"),
+    ));
+    assert!(a.is_ok() && b.is_ok());
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,156 @@
+use coi::{container, ContainerBuilder, Error, Inject, Registration, RegistrationKind};
+use std::sync::Arc;
+
+#[derive(Inject)]
+#[coi(provides Request with Request)]
+struct Request;
+
+#[derive(Inject)]
+#[coi(provides Session with Session { request })]
+struct Session {
+    #[coi(inject)]
+    request: Arc<Request>,
+}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Cache with Cache { session })]
+struct Cache {
+    #[coi(inject)]
+    session: Arc<Session>,
+}
+
+fn checked(session: RegistrationKind, cache: RegistrationKind) -> coi::Container {
+    ContainerBuilder::new()
+        .check_captive_dependencies(true)
+        .register_as(
+            "request",
+            Registration::new(RegistrationKind::Scoped, RequestProvider),
+        )
+        .register_as("session", Registration::new(session, SessionProvider))
+        .register_as("cache", Registration::new(cache, CacheProvider))
+        .build()
+}
+
+fn assert_captive<T>(result: coi::Result<T>, expected: &str, path: &[&str]) {
+    match result {
+        Err(Error::CaptiveDependency {
+            consumer,
+            dependency,
+            path: actual,
+        }) => {
+            assert_eq!(consumer, expected);
+            assert_eq!(dependency, "request");
+            assert_eq!(actual, path);
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected a captive dependency"),
+    }
+}
+
+#[test]
+fn captive_dependencies_are_allowed_by_default() {
+    let container = container! {
+        request => RequestProvider; scoped,
+        session => SessionProvider; singleton,
+    };
+
+    let scoped = container.scoped();
+    let session = scoped.resolve::<Session>("session").unwrap();
+    // The singleton keeps the request it was first constructed with.
+    assert!(Arc::ptr_eq(
+        &session.request,
+        &scoped
+            .scoped()
+            .resolve::<Session>("session")
+            .unwrap()
+            .request
+    ));
+}
+
+#[test]
+fn singleton_resolving_scoped_fails() {
+    let container = checked(RegistrationKind::Singleton, RegistrationKind::Transient);
+    assert_captive(
+        container.scoped().resolve::<Session>("session"),
+        "session",
+        &["session", "request"],
+    );
+    assert_captive(
+        container.resolve::<Session>("session"),
+        "session",
+        &["session", "request"],
+    );
+}
+
+#[test]
+fn singleton_resolving_scoped_through_transient_fails() {
+    let container = checked(RegistrationKind::Transient, RegistrationKind::Singleton);
+    assert_captive(
+        container.scoped().resolve::<Cache>("cache"),
+        "cache",
+        &["cache", "session", "request"],
+    );
+}
+
+#[test]
+fn singleton_resolving_already_resolved_scoped_fails() {
+    let container = checked(RegistrationKind::Singleton, RegistrationKind::Transient);
+    let scoped = container.scoped();
+    scoped.resolve::<Request>("request").unwrap();
+    assert_captive(
+        scoped.resolve::<Session>("session"),
+        "session",
+        &["session", "request"],
+    );
+}
+
+#[test]
+fn singleton_resolving_scoped_async_fails() {
+    let container = checked(RegistrationKind::Singleton, RegistrationKind::Transient);
+    let scoped = container.scoped();
+    assert_captive(
+        futures::executor::block_on(scoped.resolve_async::<Session>("session")),
+        "session",
+        &["session", "request"],
+    );
+}
+
+#[test]
+fn scoped_resolving_scoped_is_allowed() {
+    let container = checked(RegistrationKind::Scoped, RegistrationKind::Singleton);
+    let scoped = container.scoped();
+    let session = scoped.resolve::<Session>("session").unwrap();
+    assert!(Arc::ptr_eq(
+        &session.request,
+        &scoped.resolve::<Request>("request").unwrap()
+    ));
+}
+
+#[test]
+fn scopes_inherit_the_check() {
+    let container = checked(RegistrationKind::Singleton, RegistrationKind::Transient);
+    let scoped = container.scoped_with(|builder| builder.register("other", RequestProvider));
+    assert_captive(
+        scoped.resolve::<Session>("session"),
+        "session",
+        &["session", "request"],
+    );
+}
+
+#[test]
+fn scoped_with_singletons_can_resolve_their_own_scope() {
+    let container = checked(RegistrationKind::Singleton, RegistrationKind::Transient);
+    let scoped = container.scoped_with(|builder| {
+        builder.register_as(
+            "session",
+            Registration::new(RegistrationKind::Singleton, SessionProvider),
+        )
+    });
+
+    let session = scoped.resolve::<Session>("session").unwrap();
+    assert!(Arc::ptr_eq(
+        &session.request,
+        &scoped.resolve::<Request>("request").unwrap()
+    ));
+}
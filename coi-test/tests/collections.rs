@@ -0,0 +1,141 @@
+use coi::{ContainerBuilder, Error, Inject, Registration, RegistrationKind};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+trait Handler: Inject {
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Handler with Ping)]
+struct Ping;
+
+impl Handler for Ping {
+    fn name(&self) -> &'static str {
+        "ping"
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Handler with Echo)]
+struct Echo;
+
+impl Handler for Echo {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides Dispatcher with Dispatcher { handlers })]
+struct Dispatcher {
+    #[coi(inject_all)]
+    handlers: Vec<Arc<dyn Handler>>,
+}
+
+#[derive(Inject)]
+#[coi(provides Router with Router(plugins))]
+struct Router(#[coi(inject_all = "plugins")] Vec<Arc<dyn Handler>>);
+
+fn names(handlers: &[Arc<dyn Handler>]) -> Vec<&'static str> {
+    handlers.iter().map(|h| h.name()).collect()
+}
+
+#[test]
+fn resolve_all_returns_providers_in_registration_order() {
+    let container = ContainerBuilder::new()
+        .register_all("handlers", PingProvider)
+        .register_all("handlers", EchoProvider)
+        .build();
+
+    let handlers = container.resolve_all::<dyn Handler>("handlers").unwrap();
+    assert_eq!(names(&handlers), ["ping", "echo"]);
+}
+
+#[test]
+fn collections_do_not_clash_with_single_registrations() {
+    let container = ContainerBuilder::new()
+        .register("handlers", PingProvider)
+        .register_all("handlers", EchoProvider)
+        .build();
+
+    assert_eq!(
+        container.resolve::<dyn Handler>("handlers").unwrap().name(),
+        "ping"
+    );
+    let handlers = container.resolve_all::<dyn Handler>("handlers").unwrap();
+    assert_eq!(names(&handlers), ["echo"]);
+}
+
+#[test]
+fn unregistered_collection_is_not_found() {
+    let container = ContainerBuilder::new()
+        .register("handlers", PingProvider)
+        .build();
+
+    match container.resolve_all::<dyn Handler>("handlers") {
+        Err(Error::KeyNotFound { key, .. }) => assert_eq!(key, "handlers"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn derive_injects_collections() {
+    let container = ContainerBuilder::new()
+        .register_all("handlers", PingProvider)
+        .register_all("handlers", EchoProvider)
+        .register_all("plugins", EchoProvider)
+        .register("dispatcher", DispatcherProvider)
+        .register("router", RouterProvider)
+        .build();
+
+    let dispatcher = container.resolve::<Dispatcher>("dispatcher").unwrap();
+    assert_eq!(names(&dispatcher.handlers), ["ping", "echo"]);
+    let router = container.resolve::<Router>("router").unwrap();
+    assert_eq!(names(&router.0), ["echo"]);
+}
+
+#[test]
+fn collection_items_keep_their_registration_kind() {
+    static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counted;
+
+    impl Inject for Counted {}
+
+    impl Handler for Counted {
+        fn name(&self) -> &'static str {
+            "counted"
+        }
+    }
+
+    let container = ContainerBuilder::new()
+        .register_all_as(
+            "handlers",
+            Registration::new(RegistrationKind::Singleton, |_: &coi::Container| {
+                CREATED.fetch_add(1, Ordering::SeqCst);
+                Ok(Arc::new(Counted) as Arc<dyn Handler>)
+            }),
+        )
+        .register_all_as(
+            "handlers",
+            Registration::new(RegistrationKind::Scoped, PingProvider),
+        )
+        .register_all("handlers", EchoProvider)
+        .build();
+
+    let first = container.resolve_all::<dyn Handler>("handlers").unwrap();
+    let scoped = container.scoped();
+    let second = scoped.resolve_all::<dyn Handler>("handlers").unwrap();
+    let third = scoped.resolve_all::<dyn Handler>("handlers").unwrap();
+
+    assert_eq!(names(&second), ["counted", "ping", "echo"]);
+    assert_eq!(CREATED.load(Ordering::SeqCst), 1);
+    assert!(Arc::ptr_eq(&first[0], &second[0]));
+    assert!(!Arc::ptr_eq(&first[1], &second[1]));
+    assert!(Arc::ptr_eq(&second[1], &third[1]));
+    assert!(!Arc::ptr_eq(&second[2], &third[2]));
+}
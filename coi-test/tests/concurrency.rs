@@ -0,0 +1,155 @@
+use coi::{
+    AsyncProvide, Container, ContainerBuilder, Inject, Provide, Registration, RegistrationKind,
+};
+use futures::{executor::block_on, future::BoxFuture};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Barrier,
+};
+use std::thread;
+use std::time::Duration;
+
+const THREADS: usize = 16;
+
+struct Counted;
+
+impl Inject for Counted {}
+
+/// Counts its constructions, and takes long enough doing so that concurrent resolutions overlap.
+struct SlowProvider(Arc<AtomicUsize>);
+
+impl Provide for SlowProvider {
+    type Output = Counted;
+
+    fn provide(&self, _: &Container) -> coi::Result<Arc<Counted>> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(20));
+        Ok(Arc::new(Counted))
+    }
+}
+
+struct SlowAsyncProvider(Arc<AtomicUsize>);
+
+impl AsyncProvide for SlowAsyncProvider {
+    type Output = Counted;
+
+    fn provide<'a>(&'a self, _: &'a Container) -> BoxFuture<'a, coi::Result<Arc<Counted>>> {
+        Box::pin(async move {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            Ok(Arc::new(Counted))
+        })
+    }
+}
+
+/// Resolves `counted` from `THREADS` threads at once, returning what each of them got.
+fn resolve_concurrently<F>(container: &Container, resolve: F) -> Vec<Arc<Counted>>
+where
+    F: Fn(&Container) -> coi::Result<Arc<Counted>> + Sync,
+{
+    let barrier = Barrier::new(THREADS);
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                s.spawn(|| {
+                    barrier.wait();
+                    resolve(container).unwrap()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+fn all_same(resolved: &[Arc<Counted>]) -> bool {
+    resolved.iter().all(|c| Arc::ptr_eq(c, &resolved[0]))
+}
+
+fn container(kind: RegistrationKind) -> (Container, Arc<AtomicUsize>) {
+    let count = Arc::new(AtomicUsize::new(0));
+    let container = ContainerBuilder::new()
+        .register_as(
+            "counted",
+            Registration::new(kind, SlowProvider(Arc::clone(&count))),
+        )
+        .build();
+    (container, count)
+}
+
+#[test]
+fn concurrent_singleton_resolution_constructs_once() {
+    for _ in 0..10 {
+        let (container, count) = container(RegistrationKind::Singleton);
+        let resolved = resolve_concurrently(&container, |c| c.resolve::<Counted>("counted"));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(all_same(&resolved));
+    }
+}
+
+#[test]
+fn concurrent_singleton_resolution_from_scopes_constructs_once() {
+    let (container, count) = container(RegistrationKind::Singleton);
+    let scopes: Vec<_> = (0..THREADS).map(|_| container.scoped()).collect();
+    let next = AtomicUsize::new(0);
+    let resolved = resolve_concurrently(&container, |_| {
+        scopes[next.fetch_add(1, Ordering::SeqCst)].resolve::<Counted>("counted")
+    });
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert!(all_same(&resolved));
+}
+
+#[test]
+fn concurrent_scoped_resolution_constructs_once_per_scope() {
+    let (container, count) = container(RegistrationKind::Scoped);
+    let scoped = container.scoped();
+    let resolved = resolve_concurrently(&scoped, |c| c.resolve::<Counted>("counted"));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert!(all_same(&resolved));
+
+    let other = container.scoped().resolve::<Counted>("counted").unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+    assert!(!Arc::ptr_eq(&other, &resolved[0]));
+}
+
+#[test]
+fn concurrent_sync_and_async_resolution_constructs_once() {
+    let (container, count) = container(RegistrationKind::Singleton);
+    // Alternates between sync and async resolution.
+    let sync = AtomicBool::new(true);
+    let resolved = resolve_concurrently(&container, |c| {
+        if sync.fetch_xor(true, Ordering::SeqCst) {
+            c.resolve::<Counted>("counted")
+        } else {
+            block_on(c.resolve_async::<Counted>("counted"))
+        }
+    });
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert!(all_same(&resolved));
+}
+
+#[test]
+fn concurrent_async_resolution_across_threads_constructs_once() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let container = ContainerBuilder::new()
+        .register_async_as(
+            "counted",
+            Registration::new(
+                RegistrationKind::Singleton,
+                SlowAsyncProvider(Arc::clone(&count)),
+            ),
+        )
+        .build();
+    let resolved = resolve_concurrently(&container, |c| {
+        block_on(c.resolve_async::<Counted>("counted"))
+    });
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert!(all_same(&resolved));
+}
+
+#[test]
+fn concurrent_transient_resolution_constructs_every_time() {
+    let (container, count) = container(RegistrationKind::Transient);
+    let resolved = resolve_concurrently(&container, |c| c.resolve::<Counted>("counted"));
+    assert_eq!(count.load(Ordering::SeqCst), THREADS);
+    assert!(!all_same(&resolved));
+}
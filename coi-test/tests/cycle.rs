@@ -0,0 +1,325 @@
+use coi::{
+    container, AsyncProvide, BoxFuture, Container, ContainerBuilder, Error, Inject, Provide,
+    Registration, RegistrationKind,
+};
+use futures::executor::block_on;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::task::{Context, Poll};
+use std::thread;
+
+trait Trait1: Inject {}
+trait Trait2: Inject {}
+trait Trait3: Inject {}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn Trait1 with Impl1::new(t2))]
+struct Impl1 {
+    #[coi(inject)]
+    t2: Arc<dyn Trait2>,
+}
+
+impl Trait1 for Impl1 {}
+impl Impl1 {
+    fn new(t2: Arc<dyn Trait2>) -> Self {
+        Self { t2 }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn Trait2 with Impl2::new(t3))]
+struct Impl2 {
+    #[coi(inject)]
+    t3: Arc<dyn Trait3>,
+}
+
+impl Trait2 for Impl2 {}
+impl Impl2 {
+    fn new(t3: Arc<dyn Trait3>) -> Self {
+        Self { t3 }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn Trait3 with Impl3::new(t1))]
+struct Impl3 {
+    #[coi(inject)]
+    t1: Arc<dyn Trait1>,
+}
+
+impl Trait3 for Impl3 {}
+impl Impl3 {
+    fn new(t1: Arc<dyn Trait1>) -> Self {
+        Self { t1 }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn Trait1 as AsyncImpl1Provider with async AsyncImpl1::new(t1))]
+struct AsyncImpl1 {
+    #[coi(inject)]
+    t1: Arc<dyn Trait1>,
+}
+
+impl Trait1 for AsyncImpl1 {}
+impl AsyncImpl1 {
+    async fn new(t1: Arc<dyn Trait1>) -> Self {
+        Self { t1 }
+    }
+}
+
+fn assert_cycle<T: ?Sized>(res: coi::Result<Arc<T>>, expected: &[&str]) {
+    match res {
+        Err(Error::Cycle(path)) => assert_eq!(path, expected),
+        Err(e) => panic!("expected a cycle error, got: {}", e),
+        Ok(_) => panic!("expected a cycle error"),
+    }
+}
+
+#[test]
+fn resolving_a_cycle_errors_with_the_path() {
+    let container = container! {
+        t1 => Impl1Provider,
+        t2 => Impl2Provider,
+        t3 => Impl3Provider,
+    };
+
+    assert_cycle(
+        container.resolve::<dyn Trait1>("t1"),
+        &["t1", "t2", "t3", "t1"],
+    );
+    assert_cycle(
+        container.resolve::<dyn Trait2>("t2"),
+        &["t2", "t3", "t1", "t2"],
+    );
+}
+
+#[test]
+fn cycles_are_detected_across_scopes() {
+    let container = container! {
+        t1 => Impl1Provider; singleton,
+        t2 => Impl2Provider; scoped,
+        t3 => Impl3Provider,
+    };
+    let scoped = container.scoped();
+
+    assert_cycle(
+        scoped.resolve::<dyn Trait2>("t2"),
+        &["t2", "t3", "t1", "t2"],
+    );
+}
+
+#[test]
+fn async_cycles_are_detected() {
+    let container = ContainerBuilder::new()
+        .register_async("t1", AsyncImpl1Provider)
+        .build();
+
+    assert_cycle(
+        block_on(container.resolve_async::<dyn Trait1>("t1")),
+        &["t1", "t1"],
+    );
+}
+
+struct Locator(Container);
+
+impl Inject for Locator {}
+
+struct LocatorProvider;
+
+impl Provide for LocatorProvider {
+    type Output = Locator;
+
+    fn provide(&self, container: &Container) -> coi::Result<Arc<Locator>> {
+        Ok(Arc::new(Locator(container.clone())))
+    }
+}
+
+#[test]
+fn cloned_containers_do_not_report_false_cycles() {
+    let container = container! {
+        locator => LocatorProvider,
+    };
+    let locator = container.resolve::<Locator>("locator").unwrap();
+    assert!(locator.0.resolve::<Locator>("locator").is_ok());
+}
+
+struct Reentrant;
+
+impl Inject for Reentrant {}
+
+/// Resolves its own key again through a `Factory`.
+struct ReentrantProvider;
+
+impl Provide for ReentrantProvider {
+    type Output = Reentrant;
+
+    fn provide(&self, container: &Container) -> coi::Result<Arc<Reentrant>> {
+        container.factory::<Reentrant>("reentrant").create()
+    }
+}
+
+#[test]
+fn reentrant_resolution_through_a_factory_is_a_cycle() {
+    let container = ContainerBuilder::new()
+        .register_as(
+            "reentrant",
+            Registration::new(RegistrationKind::Singleton, ReentrantProvider),
+        )
+        .build();
+
+    assert_cycle(
+        container.resolve::<Reentrant>("reentrant"),
+        &["reentrant", "reentrant"],
+    );
+}
+
+/// Resolves its own key again through a clone of the container it was handed.
+struct ClonedProvider;
+
+impl Provide for ClonedProvider {
+    type Output = Reentrant;
+
+    fn provide(&self, container: &Container) -> coi::Result<Arc<Reentrant>> {
+        let clone = container.clone();
+        clone.resolve::<Reentrant>("cloned")
+    }
+}
+
+#[test]
+fn resolving_through_a_clone_is_checked_for_cycles() {
+    let container = ContainerBuilder::new()
+        .register("cloned", ClonedProvider)
+        .build();
+
+    assert_cycle(
+        container.resolve::<Reentrant>("cloned"),
+        &["cloned", "cloned"],
+    );
+}
+
+/// Waits for the other thread's provider to start on first use, then resolves `next`.
+struct CrossProvider {
+    next: &'static str,
+    barrier: Arc<Barrier>,
+    first: AtomicBool,
+}
+
+impl Provide for CrossProvider {
+    type Output = Reentrant;
+
+    fn provide(&self, container: &Container) -> coi::Result<Arc<Reentrant>> {
+        if self.first.swap(false, Ordering::SeqCst) {
+            self.barrier.wait();
+        }
+        container.resolve::<Reentrant>(self.next)
+    }
+}
+
+#[test]
+fn cycles_across_threads_fail_instead_of_deadlocking() {
+    let barrier = Arc::new(Barrier::new(2));
+    let provider = |next| CrossProvider {
+        next,
+        barrier: Arc::clone(&barrier),
+        first: AtomicBool::new(true),
+    };
+    let container = ContainerBuilder::new()
+        .register_as(
+            "x",
+            Registration::new(RegistrationKind::Singleton, provider("y")),
+        )
+        .register_as(
+            "y",
+            Registration::new(RegistrationKind::Singleton, provider("x")),
+        )
+        .build();
+
+    let other = {
+        let container = container.clone();
+        thread::spawn(move || container.resolve::<Reentrant>("y"))
+    };
+    let x = container.resolve::<Reentrant>("x");
+    let y = other.join().unwrap();
+    for resolved in [x, y] {
+        match resolved {
+            Err(Error::Cycle(_)) => {}
+            Err(e) => panic!("expected a cycle error, got: {}", e),
+            Ok(_) => panic!("expected a cycle error"),
+        }
+    }
+}
+
+struct YieldOnce(bool);
+
+impl Future for YieldOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// Lets the other task's provider start on first use, then resolves `next`.
+struct AsyncCrossProvider {
+    next: &'static str,
+    first: AtomicBool,
+}
+
+impl AsyncProvide for AsyncCrossProvider {
+    type Output = Reentrant;
+
+    fn provide<'a>(
+        &'a self,
+        container: &'a Container,
+    ) -> BoxFuture<'a, coi::Result<Arc<Reentrant>>> {
+        Box::pin(async move {
+            if self.first.swap(false, Ordering::SeqCst) {
+                YieldOnce(false).await;
+            }
+            container.resolve_async::<Reentrant>(self.next).await
+        })
+    }
+}
+
+#[test]
+fn cycles_across_tasks_fail_instead_of_hanging() {
+    let provider = |next| AsyncCrossProvider {
+        next,
+        first: AtomicBool::new(true),
+    };
+    let container = ContainerBuilder::new()
+        .register_async_as(
+            "x",
+            Registration::new(RegistrationKind::Singleton, provider("y")),
+        )
+        .register_async_as(
+            "y",
+            Registration::new(RegistrationKind::Singleton, provider("x")),
+        )
+        .build();
+
+    let (x, y) = block_on(futures::future::join(
+        container.resolve_async::<Reentrant>("x"),
+        container.resolve_async::<Reentrant>("y"),
+    ));
+    for resolved in [x, y] {
+        match resolved {
+            Err(Error::Cycle(_)) => {}
+            Err(e) => panic!("expected a cycle error, got: {}", e),
+            Ok(_) => panic!("expected a cycle error"),
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use coi::{container, Container, ContainerBuilder, Error, Inject, Registration, RegistrationKind};
+use futures::executor::block_on;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+trait Repo: Inject {
+    fn get(&self) -> String;
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Repo with SqlRepo)]
+struct SqlRepo;
+
+impl Repo for SqlRepo {
+    fn get(&self) -> String {
+        String::from("sql")
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Repo with async AsyncRepo::connect())]
+struct AsyncRepo;
+
+impl AsyncRepo {
+    async fn connect() -> Self {
+        AsyncRepo
+    }
+}
+
+impl Repo for AsyncRepo {
+    fn get(&self) -> String {
+        String::from("async")
+    }
+}
+
+/// Wraps a repo, tagging what it returns with `name`.
+struct Layer {
+    name: &'static str,
+    inner: Arc<dyn Repo>,
+}
+
+impl Inject for Layer {}
+
+impl Repo for Layer {
+    fn get(&self) -> String {
+        format!("{}({})", self.name, self.inner.get())
+    }
+}
+
+fn layer(name: &'static str) -> impl Fn(Arc<dyn Repo>, &Container) -> coi::Result<Arc<dyn Repo>> {
+    move |inner, _| Ok(Arc::new(Layer { name, inner }))
+}
+
+#[test]
+fn decorators_stack_with_the_last_one_outermost() {
+    let container = ContainerBuilder::new()
+        .register("repo", SqlRepoProvider)
+        .decorate("repo", layer("cache"))
+        .unwrap()
+        .decorate("repo", layer("log"))
+        .unwrap()
+        .build();
+
+    let repo = container.resolve::<dyn Repo>("repo").unwrap();
+    assert_eq!(repo.get(), "log(cache(sql))");
+}
+
+#[test]
+fn decorators_keep_the_registration_kind() {
+    let decorated = Arc::new(AtomicUsize::new(0));
+    let count = Arc::clone(&decorated);
+    let container = ContainerBuilder::new()
+        .register_as(
+            "singleton",
+            Registration::new(RegistrationKind::Singleton, SqlRepoProvider),
+        )
+        .register_as(
+            "scoped",
+            Registration::new(RegistrationKind::Scoped, SqlRepoProvider),
+        )
+        .decorate::<dyn Repo, _>("singleton", move |inner, _| {
+            count.fetch_add(1, Ordering::SeqCst);
+            Ok(inner)
+        })
+        .unwrap()
+        .decorate("scoped", layer("log"))
+        .unwrap()
+        .build();
+
+    let first = container.resolve::<dyn Repo>("singleton").unwrap();
+    let second = container.scoped().resolve::<dyn Repo>("singleton").unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(decorated.load(Ordering::SeqCst), 1);
+
+    let scoped = container.scoped();
+    let first = scoped.resolve::<dyn Repo>("scoped").unwrap();
+    assert!(Arc::ptr_eq(
+        &first,
+        &scoped.resolve::<dyn Repo>("scoped").unwrap()
+    ));
+    let other = container.scoped().resolve::<dyn Repo>("scoped").unwrap();
+    assert!(!Arc::ptr_eq(&first, &other));
+    assert_eq!(other.get(), "log(sql)");
+}
+
+#[test]
+fn decorators_resolve_from_the_container() {
+    struct Prefixed {
+        prefix: Arc<dyn Repo>,
+        inner: Arc<dyn Repo>,
+    }
+
+    impl Inject for Prefixed {}
+
+    impl Repo for Prefixed {
+        fn get(&self) -> String {
+            format!("{}:{}", self.prefix.get(), self.inner.get())
+        }
+    }
+
+    let container = container! {
+        repo => SqlRepoProvider,
+        prefix => SqlRepoProvider,
+    };
+    let container = container.with_overrides(|builder| {
+        builder
+            .register("repo", SqlRepoProvider)
+            .decorate::<dyn Repo, _>("repo", |inner, container| {
+                let prefix = container.resolve::<dyn Repo>("prefix")?;
+                Ok(Arc::new(Prefixed { prefix, inner }))
+            })
+            .unwrap()
+    });
+
+    let repo = container.resolve::<dyn Repo>("repo").unwrap();
+    assert_eq!(repo.get(), "sql:sql");
+}
+
+#[test]
+fn decorator_errors_are_returned() {
+    let container = ContainerBuilder::new()
+        .register("repo", SqlRepoProvider)
+        .decorate::<dyn Repo, _>("repo", |_, container| {
+            container.resolve::<dyn Repo>("missing")
+        })
+        .unwrap()
+        .build();
+
+    match container.resolve::<dyn Repo>("repo") {
+        Err(Error::KeyNotFound { key, path, .. }) => {
+            assert_eq!(key, "missing");
+            assert_eq!(path, ["repo", "missing"]);
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn async_providers_are_decorated() {
+    let container = ContainerBuilder::new()
+        .register_async("repo", AsyncRepoProvider)
+        .decorate("repo", layer("log"))
+        .unwrap()
+        .build();
+
+    let repo = block_on(container.resolve_async::<dyn Repo>("repo")).unwrap();
+    assert_eq!(repo.get(), "log(async)");
+}
+
+#[test]
+fn instances_are_decorated() {
+    let instance: Arc<dyn Repo> = Arc::new(SqlRepo);
+    let container = ContainerBuilder::new()
+        .register_instance("repo", instance)
+        .decorate("repo", layer("log"))
+        .unwrap()
+        .build();
+
+    let repo = container.resolve::<dyn Repo>("repo").unwrap();
+    assert_eq!(repo.get(), "log(sql)");
+    assert!(Arc::ptr_eq(
+        &repo,
+        &container.resolve::<dyn Repo>("repo").unwrap()
+    ));
+}
+
+#[test]
+fn decorate_type_decorates_type_keys() {
+    let container = ContainerBuilder::new()
+        .register_type::<dyn Repo, _>(SqlRepoProvider)
+        .decorate_type(layer("log"))
+        .unwrap()
+        .build();
+
+    let repo = container.resolve_type::<dyn Repo>().unwrap();
+    assert_eq!(repo.get(), "log(sql)");
+}
+
+#[test]
+fn registering_again_replaces_the_decorators() {
+    let container = ContainerBuilder::new()
+        .register("repo", SqlRepoProvider)
+        .decorate("repo", layer("log"))
+        .unwrap()
+        .register("repo", SqlRepoProvider)
+        .build();
+
+    let repo = container.resolve::<dyn Repo>("repo").unwrap();
+    assert_eq!(repo.get(), "sql");
+}
+
+#[test]
+fn decorating_an_unregistered_key_fails() {
+    match ContainerBuilder::new().decorate("repo", layer("log")) {
+        Err(Error::KeyNotFound { key, .. }) => assert_eq!(key, "repo"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn decorating_as_another_type_fails() {
+    let builder = ContainerBuilder::new().register("repo", SqlRepoProvider);
+    match builder.decorate::<SqlRepo, _>("repo", |inner, _| Ok(inner)) {
+        Err(Error::TypeMismatch { key, .. }) => assert_eq!(key, "repo"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
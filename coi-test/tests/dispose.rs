@@ -0,0 +1,239 @@
+use coi::{
+    container, AsyncDispose, BoxFuture, Container, ContainerBuilder, Dispose, Error, Inject,
+    Registration, RegistrationKind,
+};
+use futures::executor::block_on;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct Log(Mutex<Vec<String>>);
+
+impl Inject for Log {}
+
+impl Log {
+    fn push(&self, entry: &str) {
+        self.0.lock().unwrap().push(String::from(entry));
+    }
+
+    fn entries(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+struct LogProvider(Arc<Log>);
+
+impl coi::Provide for LogProvider {
+    type Output = Log;
+
+    fn provide(&self, _: &Container) -> coi::Result<Arc<Log>> {
+        Ok(Arc::clone(&self.0))
+    }
+}
+
+trait Repo: Inject + Dispose {}
+
+#[derive(Inject)]
+#[coi(provides dyn Repo with Db { log })]
+#[coi(dispose)]
+struct Db {
+    #[coi(inject)]
+    log: Arc<Log>,
+}
+
+impl Repo for Db {}
+
+impl Dispose for Db {
+    fn dispose(&self) -> coi::Result<()> {
+        self.log.push("db");
+        Ok(())
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides Transaction with Transaction { log, repo })]
+#[coi(dispose)]
+struct Transaction {
+    #[coi(inject)]
+    log: Arc<Log>,
+    #[allow(dead_code)]
+    #[coi(inject)]
+    repo: Arc<dyn Repo>,
+}
+
+impl Dispose for Transaction {
+    fn dispose(&self) -> coi::Result<()> {
+        self.log.push("tx");
+        Ok(())
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides Failing with Failing)]
+#[coi(dispose)]
+struct Failing;
+
+impl Dispose for Failing {
+    fn dispose(&self) -> coi::Result<()> {
+        Err(Error::Inner("rollback failed".into()))
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides Session with Session { log })]
+#[coi(dispose async)]
+struct Session {
+    #[coi(inject)]
+    log: Arc<Log>,
+}
+
+impl AsyncDispose for Session {
+    fn dispose<'a>(&'a self) -> BoxFuture<'a, coi::Result<()>> {
+        Box::pin(async move {
+            self.log.push("session");
+            Ok(())
+        })
+    }
+}
+
+fn scoped(log: &Arc<Log>) -> ContainerBuilder {
+    ContainerBuilder::new()
+        .register_as(
+            "log",
+            Registration::new(RegistrationKind::Singleton, LogProvider(Arc::clone(log))),
+        )
+        .register_as(
+            "repo",
+            Registration::new(RegistrationKind::Scoped, DbProvider),
+        )
+        .register_as(
+            "tx",
+            Registration::new(RegistrationKind::Scoped, TransactionProvider),
+        )
+}
+
+#[test]
+fn dispose_runs_in_reverse_creation_order() {
+    let log = Arc::new(Log::default());
+    let container = scoped(&log).build();
+    let scope = container.scoped();
+    let _tx = scope.resolve::<Transaction>("tx").unwrap();
+
+    scope.dispose().unwrap();
+    assert_eq!(log.entries(), ["tx", "db"]);
+
+    // Disposed instances are gone, and aren't disposed of again.
+    scope.dispose().unwrap();
+    assert_eq!(log.entries().len(), 2);
+    let _tx = scope.resolve::<Transaction>("tx").unwrap();
+    scope.dispose().unwrap();
+    assert_eq!(log.entries(), ["tx", "db", "tx", "db"]);
+}
+
+#[test]
+fn dropping_a_scope_disposes_its_instances() {
+    let log = Arc::new(Log::default());
+    let container = scoped(&log).build();
+    {
+        let scope = container.scoped();
+        let _tx = scope.resolve::<Transaction>("tx").unwrap();
+        assert!(log.entries().is_empty());
+    }
+    assert_eq!(log.entries(), ["tx", "db"]);
+}
+
+#[test]
+fn transients_and_parent_instances_are_not_disposed() {
+    let log = Arc::new(Log::default());
+    let container = container! {
+        log => LogProvider(Arc::clone(&log)); singleton,
+        repo => DbProvider; singleton,
+        tx => TransactionProvider,
+    };
+    let scope = container.scoped();
+    let _tx = scope.resolve::<Transaction>("tx").unwrap();
+
+    scope.dispose().unwrap();
+    assert!(log.entries().is_empty());
+    container.dispose().unwrap();
+    assert_eq!(log.entries(), ["db"]);
+}
+
+#[test]
+fn dispose_errors_are_aggregated() {
+    let log = Arc::new(Log::default());
+    let container = scoped(&log)
+        .register_as(
+            "failing",
+            Registration::new(RegistrationKind::Singleton, FailingProvider),
+        )
+        .register_as(
+            "session",
+            Registration::new(RegistrationKind::Singleton, SessionProvider),
+        )
+        .build();
+    let _failing = container.resolve::<Failing>("failing").unwrap();
+    let _tx = container.resolve::<Transaction>("tx").unwrap();
+
+    match container.dispose() {
+        Err(Error::Dispose(errors)) => {
+            let keys: Vec<_> = errors.iter().map(|(key, _)| key.as_str()).collect();
+            assert_eq!(keys, ["failing"]);
+            assert!(matches!(errors[0].1, Error::Inner(_)));
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(()) => panic!("expected an error"),
+    }
+    // Every disposer still ran.
+    assert_eq!(log.entries(), ["tx", "db"]);
+}
+
+#[test]
+fn dispose_leaves_async_disposers_to_dispose_async() {
+    let log = Arc::new(Log::default());
+    let container = scoped(&log)
+        .register_as(
+            "session",
+            Registration::new(RegistrationKind::Singleton, SessionProvider),
+        )
+        .build();
+    let session = container.resolve::<Session>("session").unwrap();
+    let _tx = container.resolve::<Transaction>("tx").unwrap();
+
+    match container.dispose() {
+        Err(Error::Dispose(errors)) => {
+            assert_eq!(errors.len(), 1);
+            match &errors[0] {
+                (key, Error::RequiresAsyncDispose(_)) => assert_eq!(key, "session"),
+                (_, e) => panic!("unexpected error: {}", e),
+            }
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(()) => panic!("expected an error"),
+    }
+    assert_eq!(log.entries(), ["tx", "db"]);
+    // The session is still cached, and disposed of once the container is disposed of async.
+    assert!(Arc::ptr_eq(
+        &session,
+        &container.resolve::<Session>("session").unwrap()
+    ));
+
+    block_on(container.dispose_async()).unwrap();
+    assert_eq!(log.entries(), ["tx", "db", "session"]);
+}
+
+#[test]
+fn dispose_async_awaits_async_disposers() {
+    let log = Arc::new(Log::default());
+    let container = scoped(&log)
+        .register_as(
+            "session",
+            Registration::new(RegistrationKind::Scoped, SessionProvider),
+        )
+        .build();
+    let scope = container.scoped();
+    let _session = scope.resolve::<Session>("session").unwrap();
+    let _tx = scope.resolve::<Transaction>("tx").unwrap();
+
+    block_on(scope.dispose_async()).unwrap();
+    assert_eq!(log.entries(), ["tx", "db", "session"]);
+}
@@ -0,0 +1,107 @@
+use coi::{ContainerBuilder, DuplicatePolicy, Error, Inject, Registration, RegistrationKind};
+
+trait Logger: Inject {
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Logger with Stdout)]
+struct Stdout;
+
+impl Logger for Stdout {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Logger with File)]
+struct File;
+
+impl Logger for File {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+}
+
+fn logger_name(builder: ContainerBuilder) -> &'static str {
+    builder
+        .build()
+        .resolve::<dyn Logger>("logger")
+        .unwrap()
+        .name()
+}
+
+#[test]
+fn duplicates_are_replaced_by_default() {
+    let builder = ContainerBuilder::new()
+        .register("logger", StdoutProvider)
+        .register("logger", FileProvider);
+    assert_eq!(logger_name(builder), "file");
+}
+
+#[test]
+fn keep_first_ignores_later_registrations() {
+    let builder = ContainerBuilder::new()
+        .duplicate_policy(DuplicatePolicy::KeepFirst)
+        .register("logger", StdoutProvider)
+        .register_as(
+            "logger",
+            Registration::new(RegistrationKind::Singleton, FileProvider),
+        );
+    assert_eq!(logger_name(builder), "stdout");
+}
+
+#[test]
+fn error_policy_lists_every_duplicate_with_locations() {
+    let first_line = line!() + 3;
+    let result = ContainerBuilder::new()
+        .duplicate_policy(DuplicatePolicy::Error)
+        .register("logger", StdoutProvider)
+        .register("db", StdoutProvider)
+        .register("logger", FileProvider)
+        .register_type::<dyn Logger, _>(StdoutProvider)
+        .register_type::<dyn Logger, _>(FileProvider)
+        .register("unique", FileProvider)
+        .try_build();
+
+    let duplicates = match result {
+        Err(Error::DuplicateKeys(duplicates)) => duplicates,
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected duplicate keys"),
+    };
+    assert_eq!(duplicates.len(), 2);
+    assert_eq!(duplicates[0].key, "logger");
+    assert_eq!(duplicates[0].first.file(), file!());
+    assert_eq!(duplicates[0].first.line(), first_line);
+    assert_eq!(duplicates[0].second.line(), first_line + 2);
+    assert_eq!(duplicates[1].key, "dyn duplicates::Logger");
+    assert_eq!(duplicates[1].first.line(), first_line + 3);
+    assert_eq!(duplicates[1].second.line(), first_line + 4);
+
+    let message = Error::DuplicateKeys(duplicates).to_string();
+    assert!(message.starts_with("Duplicate keys: logger (registered at "));
+}
+
+#[test]
+fn overrides_are_not_duplicates() {
+    let builder = ContainerBuilder::new()
+        .duplicate_policy(DuplicatePolicy::Error)
+        .register("logger", StdoutProvider)
+        .override_with(
+            "logger",
+            Registration::new(RegistrationKind::Transient, FileProvider),
+        )
+        .unwrap();
+    assert_eq!(logger_name(builder), "file");
+}
+
+#[test]
+#[should_panic(expected = "Duplicate keys: logger")]
+fn build_panics_on_duplicates() {
+    ContainerBuilder::new()
+        .duplicate_policy(DuplicatePolicy::Error)
+        .register("logger", StdoutProvider)
+        .register("logger", FileProvider)
+        .build();
+}
@@ -0,0 +1,127 @@
+use coi::{Container, ContainerBuilder, Error, Inject, Module, Registration, RegistrationKind};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+#[derive(Inject)]
+#[coi(provides Config with Config)]
+struct Config;
+
+#[derive(Inject)]
+#[coi(provides Client with Client { config })]
+struct Client {
+    #[allow(dead_code)]
+    #[coi(inject)]
+    config: Arc<Config>,
+}
+
+fn singleton<P>(provider: P) -> Registration<P> {
+    Registration::new(RegistrationKind::Singleton, provider)
+}
+
+#[test]
+fn build_eager_constructs_singletons_in_dependency_order() {
+    let order = Arc::new(Mutex::new(vec![]));
+    let config_order = Arc::clone(&order);
+    let client_order = Arc::clone(&order);
+    let container = ContainerBuilder::new()
+        .register_as(
+            "client",
+            singleton(move |container: &Container| {
+                let config = container.resolve::<Config>("config")?;
+                client_order.lock().unwrap().push("client");
+                Ok(Arc::new(Client { config }))
+            }),
+        )
+        .register_as(
+            "config",
+            singleton(move |_: &Container| {
+                config_order.lock().unwrap().push("config");
+                Ok(Arc::new(Config))
+            }),
+        )
+        .build_eager()
+        .unwrap();
+
+    assert_eq!(*order.lock().unwrap(), ["config", "client"]);
+    container.resolve::<Client>("client").unwrap();
+    assert_eq!(order.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn build_eager_skips_scoped_and_transient_registrations() {
+    static CREATED: AtomicUsize = AtomicUsize::new(0);
+    let count = |_: &Container| {
+        CREATED.fetch_add(1, Ordering::SeqCst);
+        Ok(Arc::new(Config))
+    };
+    ContainerBuilder::new()
+        .register("transient", count)
+        .register_as("scoped", Registration::new(RegistrationKind::Scoped, count))
+        // Registering a key again replaces its registration kind too.
+        .register_as("replaced", singleton(count))
+        .register("replaced", count)
+        .build_eager()
+        .unwrap();
+
+    assert_eq!(CREATED.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn build_eager_collects_every_failure() {
+    let result = ContainerBuilder::new()
+        .register_as("client", singleton(ClientProvider))
+        .register_as(
+            "failing",
+            singleton(|_: &Container| -> coi::Result<Arc<Config>> {
+                Err(Error::Inner("no socket".into()))
+            }),
+        )
+        .build_eager();
+
+    match result {
+        Err(Error::Eager(mut errors)) => {
+            errors.sort_by(|(a, _), (b, _)| a.cmp(b));
+            assert_eq!(errors.len(), 2);
+            assert_eq!(errors[0].0, "client");
+            assert!(matches!(
+                &errors[0].1,
+                Error::KeyNotFound { key, .. } if key == "config"
+            ));
+            assert_eq!(errors[1].0, "failing");
+            assert!(matches!(errors[1].1, Error::Inner(_)));
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+struct AuthModule;
+
+impl Module for AuthModule {
+    fn name(&self) -> &'static str {
+        "auth"
+    }
+
+    fn exports(&self) -> &'static [&'static str] {
+        &["client"]
+    }
+
+    fn register(&self, builder: ContainerBuilder) -> ContainerBuilder {
+        builder
+            .register_as("config", singleton(ConfigProvider))
+            .register_as("client", singleton(ClientProvider))
+    }
+}
+
+#[test]
+fn build_eager_constructs_singletons_a_module_didnt_export() {
+    let container = ContainerBuilder::new()
+        .add_module(AuthModule)
+        .build_eager()
+        .unwrap();
+
+    assert!(container.resolve::<Client>("auth::client").is_ok());
+    assert!(container.resolve::<Config>("auth::config").is_err());
+}
@@ -0,0 +1,102 @@
+use coi::{container, Error, Inject};
+use std::sync::Arc;
+
+trait Db: Inject {}
+trait Repo: Inject {}
+trait UserService: Inject {}
+trait App: Inject {}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn Repo with RepoImpl { db })]
+struct RepoImpl {
+    #[coi(inject)]
+    db: Arc<dyn Db>,
+}
+
+impl Repo for RepoImpl {}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn UserService with UserServiceImpl { repo })]
+struct UserServiceImpl {
+    #[coi(inject)]
+    repo: Arc<dyn Repo>,
+}
+
+impl UserService for UserServiceImpl {}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn App with AppImpl { user_service })]
+struct AppImpl {
+    #[coi(inject)]
+    user_service: Arc<dyn UserService>,
+}
+
+impl App for AppImpl {}
+
+#[test]
+fn key_not_found_reports_resolution_path() {
+    let container = container! {
+        app => AppImplProvider; singleton,
+        user_service => UserServiceImplProvider; scoped,
+        repo => RepoImplProvider,
+    };
+    let scoped = container.scoped();
+
+    let error = scoped.resolve::<dyn App>("app").err().unwrap();
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "Key not found: db (requested as `{}`, resolving app -> user_service -> repo -> db)",
+            std::any::type_name::<dyn Db>()
+        )
+    );
+    match error {
+        Error::KeyNotFound {
+            key,
+            type_name,
+            path,
+        } => {
+            assert_eq!(key, "db");
+            assert_eq!(type_name, std::any::type_name::<dyn Db>());
+            assert_eq!(path, ["app", "user_service", "repo", "db"]);
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Db with DbImpl)]
+struct DbImpl;
+
+impl Db for DbImpl {}
+
+#[test]
+fn type_mismatch_reports_requested_type_and_path() {
+    let container = container! {
+        user_service => UserServiceImplProvider,
+        repo => DbImplProvider; singleton,
+    };
+
+    let assert_mismatch = || match container.resolve::<dyn UserService>("user_service") {
+        Err(Error::TypeMismatch {
+            key,
+            type_name,
+            path,
+        }) => {
+            assert_eq!(key, "repo");
+            assert_eq!(type_name, std::any::type_name::<dyn Repo>());
+            assert_eq!(path, ["user_service", "repo"]);
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected a type mismatch"),
+    };
+
+    // Mismatches are reported both against the registered provider, and against the
+    // cached singleton.
+    assert_mismatch();
+    container.resolve::<dyn Db>("repo").unwrap();
+    assert_mismatch();
+}
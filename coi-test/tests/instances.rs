@@ -0,0 +1,148 @@
+use coi::{container, Container, ContainerBuilder, Inject, Registration, RegistrationKind};
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+struct Config {
+    port: u16,
+}
+
+impl Inject for Config {}
+
+#[derive(Inject)]
+#[coi(provides Server with Server { config })]
+struct Server {
+    #[coi(inject)]
+    config: Arc<Config>,
+}
+
+fn config() -> Arc<Config> {
+    Arc::new(Config { port: 8080 })
+}
+
+fn provide_config(_: &Container) -> coi::Result<Arc<Config>> {
+    Ok(config())
+}
+
+#[test]
+fn instance_is_resolved_as_registered() {
+    let config = config();
+    let container = ContainerBuilder::new()
+        .register_instance("config", Arc::clone(&config))
+        .register("server", ServerProvider)
+        .build();
+
+    let resolved = container.resolve::<Config>("config").unwrap();
+    assert!(Arc::ptr_eq(&config, &resolved));
+    let server = container.resolve::<Server>("server").unwrap();
+    assert!(Arc::ptr_eq(&config, &server.config));
+}
+
+#[test]
+fn instance_is_shared_with_scopes() {
+    let config = config();
+    let container = container! {
+        config => instance(Arc::clone(&config)),
+        server => ServerProvider; scoped,
+    };
+
+    let scoped = container.scoped();
+    let resolved = scoped.resolve::<Config>("config").unwrap();
+    assert!(Arc::ptr_eq(&config, &resolved));
+    assert!(Arc::ptr_eq(
+        &config,
+        &scoped.resolve::<Server>("server").unwrap().config
+    ));
+}
+
+#[test]
+fn instance_is_resolved_async() {
+    let config = config();
+    let container = ContainerBuilder::new()
+        .register_instance("config", Arc::clone(&config))
+        .build();
+
+    let resolved = futures::executor::block_on(container.resolve_async::<Config>("config"));
+    assert!(Arc::ptr_eq(&config, &resolved.unwrap()));
+}
+
+#[test]
+fn registering_a_provider_replaces_an_instance() {
+    let container = ContainerBuilder::new()
+        .register_instance("config", config())
+        .register_as(
+            "config",
+            Registration::new(RegistrationKind::Singleton, |_: &Container| {
+                Ok(Arc::new(Config { port: 9090 }))
+            }),
+        )
+        .build();
+
+    assert_eq!(container.resolve::<Config>("config").unwrap().port, 9090);
+}
+
+#[test]
+fn overriding_with_an_instance() {
+    let container = container! {
+        config => |_: &Container| Ok(Arc::new(Config { port: 9090 })),
+    };
+    let config = config();
+    let overridden = container
+        .with_overrides(|builder| builder.register_instance("config", Arc::clone(&config)));
+
+    assert_eq!(container.resolve::<Config>("config").unwrap().port, 9090);
+    let resolved = overridden.resolve::<Config>("config").unwrap();
+    assert!(Arc::ptr_eq(&config, &resolved));
+}
+
+#[test]
+fn large_containers_expand_without_hitting_the_recursion_limit() {
+    let container = container! {
+        k0 => provide_config, k1 => provide_config, k2 => provide_config, k3 => provide_config, k4 => provide_config, k5 => provide_config,
+        k6 => provide_config, k7 => provide_config, k8 => provide_config, k9 => provide_config, k10 => provide_config, k11 => provide_config,
+        k12 => provide_config, k13 => provide_config, k14 => provide_config, k15 => provide_config, k16 => provide_config, k17 => provide_config,
+        k18 => provide_config, k19 => provide_config, k20 => provide_config, k21 => provide_config, k22 => provide_config, k23 => provide_config,
+        k24 => provide_config, k25 => provide_config, k26 => provide_config, k27 => provide_config, k28 => provide_config, k29 => provide_config,
+        k30 => provide_config, k31 => provide_config, k32 => provide_config, k33 => provide_config, k34 => provide_config, k35 => provide_config,
+        k36 => provide_config, k37 => provide_config, k38 => provide_config, k39 => provide_config, k40 => provide_config, k41 => provide_config,
+        k42 => provide_config, k43 => provide_config, k44 => provide_config, k45 => provide_config, k46 => provide_config, k47 => provide_config,
+        k48 => provide_config, k49 => provide_config, k50 => provide_config, k51 => provide_config, k52 => provide_config, k53 => provide_config,
+        k54 => provide_config, k55 => provide_config, k56 => provide_config, k57 => provide_config, k58 => provide_config, k59 => provide_config,
+        k60 => provide_config, k61 => provide_config, k62 => provide_config, k63 => provide_config, k64 => provide_config, k65 => provide_config,
+        k66 => provide_config, k67 => provide_config, k68 => provide_config, k69 => provide_config, k70 => provide_config, k71 => provide_config,
+        k72 => provide_config, k73 => provide_config, k74 => provide_config, k75 => provide_config, k76 => provide_config, k77 => provide_config,
+        k78 => provide_config, k79 => provide_config, k80 => provide_config, k81 => provide_config, k82 => provide_config, k83 => provide_config,
+        k84 => provide_config, k85 => provide_config, k86 => provide_config, k87 => provide_config, k88 => provide_config, k89 => provide_config,
+        k90 => provide_config, k91 => provide_config, k92 => provide_config, k93 => provide_config, k94 => provide_config, k95 => provide_config,
+        k96 => provide_config, k97 => provide_config, k98 => provide_config, k99 => provide_config, k100 => provide_config, k101 => provide_config,
+        k102 => provide_config, k103 => provide_config, k104 => provide_config, k105 => provide_config, k106 => provide_config, k107 => provide_config,
+        k108 => provide_config, k109 => provide_config, k110 => provide_config, k111 => provide_config, k112 => provide_config, k113 => provide_config,
+        k114 => provide_config, k115 => provide_config, k116 => provide_config, k117 => provide_config, k118 => provide_config, k119 => provide_config,
+        k120 => provide_config, k121 => provide_config, k122 => provide_config, k123 => provide_config, k124 => provide_config, k125 => provide_config,
+        k126 => provide_config, k127 => provide_config, k128 => provide_config, k129 => provide_config,
+        config => instance(config()),
+    };
+    assert!(container.resolve::<Config>("k129").is_ok());
+    assert_eq!(container.resolve::<Config>("config").unwrap().port, 8080);
+}
+
+#[test]
+fn variables_named_instance_are_providers() {
+    let instance = provide_config;
+    let container = container! {
+        first => instance,
+        server => ServerProvider; scoped,
+        config => instance(config()),
+        last => instance; singleton,
+    };
+
+    assert_eq!(container.resolve::<Config>("first").unwrap().port, 8080);
+    assert_eq!(container.resolve::<Config>("last").unwrap().port, 8080);
+    assert!(Arc::ptr_eq(
+        &container.resolve::<Config>("config").unwrap(),
+        &container
+            .scoped()
+            .resolve::<Server>("server")
+            .unwrap()
+            .config
+    ));
+}
@@ -0,0 +1,163 @@
+use coi::{Container, ContainerBuilder, Dispose, Inject, Module, Registration, RegistrationKind};
+use std::sync::Arc;
+
+#[derive(Inject)]
+#[coi(provides Config with Config)]
+struct Config;
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Server with Server { config, metrics })]
+struct Server {
+    #[coi(inject)]
+    config: Arc<Config>,
+    #[coi(inject)]
+    metrics: Option<Arc<Config>>,
+}
+
+struct AuthModule;
+
+impl Module for AuthModule {
+    fn name(&self) -> &'static str {
+        "auth"
+    }
+
+    fn exports(&self) -> &'static [&'static str] {
+        &["service"]
+    }
+
+    fn register(&self, builder: ContainerBuilder) -> ContainerBuilder {
+        builder
+            .register("repo", ConfigProvider)
+            .register("service", ConfigProvider)
+    }
+}
+
+fn container() -> Container {
+    ContainerBuilder::new()
+        .register_as(
+            "config",
+            Registration::new(RegistrationKind::Singleton, ConfigProvider),
+        )
+        .register_as(
+            "request",
+            Registration::new(RegistrationKind::Scoped, ConfigProvider),
+        )
+        .register("server", ServerProvider)
+        .build()
+}
+
+#[test]
+fn contains_registered_keys() {
+    let container = ContainerBuilder::new()
+        .register("config", ConfigProvider)
+        .register_type::<Server, _>(ServerProvider)
+        .add_module(AuthModule)
+        .build();
+
+    assert!(container.contains("config"));
+    assert!(container.scoped().contains("config"));
+    assert!(container.contains_type::<Server>());
+    assert!(container.contains("auth::service"));
+    assert!(!container.contains("auth::repo"));
+    assert!(!container.contains("server"));
+    assert!(!container.contains_type::<Config>());
+}
+
+#[test]
+fn registrations_are_listed_by_key() {
+    let registrations = container().registrations();
+
+    let keys: Vec<_> = registrations.iter().map(|r| r.key.as_str()).collect();
+    assert_eq!(keys, ["config", "request", "server"]);
+    assert!(matches!(registrations[0].kind, RegistrationKind::Singleton));
+    assert!(registrations[0].type_name.ends_with("Config"));
+    assert_eq!(registrations[0].dependencies, Some(&[][..]));
+    assert!(matches!(registrations[1].kind, RegistrationKind::Scoped));
+    let server = &registrations[2];
+    assert!(matches!(server.kind, RegistrationKind::Transient));
+    assert!(server.type_name.ends_with("Server"));
+    assert_eq!(server.dependencies, Some(&["config"][..]));
+    assert_eq!(server.optional_dependencies, ["metrics"]);
+}
+
+#[test]
+fn registrations_include_parents_and_collections() {
+    let container = container().scoped_with(|builder| {
+        builder
+            .register("server", |_: &Container| Ok(Arc::new(Config)))
+            .register_all("handlers", ConfigProvider)
+            .register_all("handlers", ConfigProvider)
+    });
+
+    let registrations = container.registrations();
+    let keys: Vec<_> = registrations.iter().map(|r| r.key.as_str()).collect();
+    assert_eq!(
+        keys,
+        ["config", "handlers[0]", "handlers[1]", "request", "server"]
+    );
+    let server = &registrations[4];
+    assert!(server.type_name.ends_with("Config"));
+    assert_eq!(server.dependencies, None);
+}
+
+#[test]
+fn registrations_skip_keys_a_module_doesnt_export() {
+    let container = ContainerBuilder::new().add_module(AuthModule).build();
+
+    let keys: Vec<_> = container
+        .registrations()
+        .into_iter()
+        .map(|r| r.key)
+        .collect();
+    assert_eq!(keys, ["auth::service"]);
+}
+
+#[test]
+fn resolved_keys_lists_the_instances_each_container_holds() {
+    let container = container();
+    assert!(container.resolved_keys().is_empty());
+
+    let scoped = container.scoped();
+    scoped.resolve::<Server>("server").unwrap();
+    scoped.resolve::<Config>("request").unwrap();
+    assert_eq!(container.resolved_keys(), ["config"]);
+    assert_eq!(scoped.resolved_keys(), ["request"]);
+    assert!(container.scoped().resolved_keys().is_empty());
+}
+
+#[test]
+fn resolved_keys_include_registered_instances() {
+    let container = ContainerBuilder::new()
+        .register_instance("config", Arc::new(Config))
+        .register("server", ServerProvider)
+        .build();
+
+    assert_eq!(container.resolved_keys(), ["config"]);
+}
+
+#[test]
+fn disposed_instances_are_no_longer_listed() {
+    #[derive(Inject)]
+    #[coi(provides Pool with Pool)]
+    #[coi(dispose)]
+    struct Pool;
+
+    impl Dispose for Pool {
+        fn dispose(&self) -> coi::Result<()> {
+            Ok(())
+        }
+    }
+
+    let container = ContainerBuilder::new()
+        .register_as(
+            "pool",
+            Registration::new(RegistrationKind::Singleton, PoolProvider),
+        )
+        .build();
+    container.resolve::<Pool>("pool").unwrap();
+    assert_eq!(container.resolved_keys(), ["pool"]);
+
+    container.dispose().unwrap();
+    assert!(container.resolved_keys().is_empty());
+}
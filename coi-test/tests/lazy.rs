@@ -0,0 +1,144 @@
+use coi::{container, ContainerBuilder, Error, Factory, Inject, Lazy};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+trait Report: Inject {
+    fn render(&self) -> &'static str;
+}
+
+struct PdfReport;
+
+impl Inject for PdfReport {}
+
+impl Report for PdfReport {
+    fn render(&self) -> &'static str {
+        "pdf"
+    }
+}
+
+fn pdf_report(_: &coi::Container) -> coi::Result<Arc<dyn Report>> {
+    REPORTS.fetch_add(1, Ordering::SeqCst);
+    Ok(Arc::new(PdfReport))
+}
+
+#[derive(Inject)]
+#[coi(provides Exporter with Exporter { report })]
+struct Exporter {
+    #[coi(inject)]
+    report: Lazy<dyn Report>,
+}
+
+#[derive(Inject)]
+#[coi(provides Connection with Connection)]
+struct Connection;
+
+#[derive(Inject)]
+#[coi(provides Pool with Pool(connection, _1))]
+struct Pool(
+    #[coi(inject = "connection")] Factory<Connection>,
+    #[coi(inject)] Lazy<Connection>,
+);
+
+#[derive(Inject)]
+#[coi(provides Client with Client { connection, factory })]
+struct Client {
+    #[coi(inject)]
+    connection: coi::Lazy<Connection>,
+    #[coi(inject)]
+    factory: coi::Factory<Connection>,
+}
+
+#[test]
+fn lazy_resolves_on_first_use_and_caches() {
+    let container = ContainerBuilder::new()
+        .register("report", pdf_report)
+        .register("exporter", ExporterProvider)
+        .build();
+
+    let exporter = container.resolve::<Exporter>("exporter").unwrap();
+    assert_eq!(REPORTS.load(Ordering::SeqCst), 0);
+    assert_eq!(exporter.report.get().unwrap().render(), "pdf");
+    assert_eq!(exporter.report.get().unwrap().render(), "pdf");
+    assert_eq!(REPORTS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn factory_creates_a_new_transient_instance_per_call() {
+    let container = ContainerBuilder::new()
+        .register("connection", ConnectionProvider)
+        .register_type::<Connection, _>(ConnectionProvider)
+        .register("pool", PoolProvider)
+        .build();
+
+    let pool = container.resolve::<Pool>("pool").unwrap();
+    let first = pool.0.create().unwrap();
+    let second = pool.0.create().unwrap();
+    assert!(!Arc::ptr_eq(&first, &second));
+    assert!(Arc::ptr_eq(pool.1.get().unwrap(), pool.1.get().unwrap()));
+}
+
+#[test]
+fn qualified_handles_are_injected() {
+    let container = container! {
+        connection => ConnectionProvider,
+        factory => ConnectionProvider,
+        client => ClientProvider,
+    };
+
+    let client = container.resolve::<Client>("client").unwrap();
+    assert!(client.connection.get().is_ok());
+    assert!(client.factory.create().is_ok());
+}
+
+#[test]
+fn lazy_reports_missing_keys_when_used() {
+    let container = container! {
+        exporter => ExporterProvider,
+    };
+
+    let exporter = container.resolve::<Exporter>("exporter").unwrap();
+    match exporter.report.get() {
+        Err(Error::KeyNotFound { key, .. }) => assert_eq!(key, "report"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn handles_do_not_keep_the_container_alive() {
+    let container = container! {
+        connection => ConnectionProvider,
+    };
+    let lazy = container.lazy::<Connection>("connection");
+    let factory = container.factory::<Connection>("connection");
+    drop(container);
+
+    match lazy.get() {
+        Err(Error::ContainerDropped(key)) => assert_eq!(key, "connection"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+    assert!(matches!(factory.create(), Err(Error::ContainerDropped(_))));
+}
+
+#[test]
+fn handles_resolve_from_the_scope_they_were_created_in() {
+    let container = container! {
+        connection => ConnectionProvider; scoped,
+    };
+    let scoped = container.scoped();
+    let factory = scoped.factory::<Connection>("connection");
+    let lazy = scoped.lazy::<Connection>("connection");
+
+    let first = factory.create().unwrap();
+    assert!(Arc::ptr_eq(&first, &factory.create().unwrap()));
+    assert!(Arc::ptr_eq(&first, lazy.get().unwrap()));
+    assert!(!Arc::ptr_eq(
+        &first,
+        &container.resolve::<Connection>("connection").unwrap()
+    ));
+}
@@ -0,0 +1,222 @@
+use coi::{ContainerBuilder, Error, Inject, Lazy, Module, Registration, RegistrationKind};
+use std::sync::Arc;
+
+#[derive(Inject)]
+#[coi(provides Config with Config)]
+struct Config;
+
+#[derive(Inject)]
+#[coi(provides Repo with Repo("auth"))]
+struct Repo(&'static str);
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Repo with Repo("billing"))]
+struct BillingRepo;
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Service with Service { repo, config })]
+struct Service {
+    #[coi(inject)]
+    repo: Arc<Repo>,
+    #[coi(inject)]
+    config: Arc<Config>,
+}
+
+#[derive(Inject)]
+#[coi(provides Billing with Billing { service, repo })]
+struct Billing {
+    #[coi(inject = "auth::service")]
+    service: Arc<Service>,
+    #[coi(inject)]
+    repo: Lazy<Repo>,
+}
+
+struct AuthModule;
+
+impl Module for AuthModule {
+    fn name(&self) -> &'static str {
+        "auth"
+    }
+
+    fn exports(&self) -> &'static [&'static str] {
+        &["service"]
+    }
+
+    fn register(&self, builder: ContainerBuilder) -> ContainerBuilder {
+        builder
+            .register_as(
+                "repo",
+                Registration::new(RegistrationKind::Singleton, RepoProvider),
+            )
+            .register("service", ServiceProvider)
+    }
+}
+
+struct BillingModule;
+
+impl Module for BillingModule {
+    fn name(&self) -> &'static str {
+        "billing"
+    }
+
+    fn exports(&self) -> &'static [&'static str] {
+        &["billing", "repo"]
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["auth"]
+    }
+
+    fn register(&self, builder: ContainerBuilder) -> ContainerBuilder {
+        builder
+            .register("repo", BillingRepoProvider)
+            .register("billing", BillingProvider)
+    }
+}
+
+fn app() -> ContainerBuilder {
+    ContainerBuilder::new()
+        .register("config", ConfigProvider)
+        .add_module(AuthModule)
+        .add_module(BillingModule)
+}
+
+#[test]
+fn module_keys_do_not_collide() {
+    let container = app().build();
+
+    let billing = container.resolve::<Billing>("billing::billing").unwrap();
+    assert_eq!(billing.service.repo.0, "auth");
+    assert_eq!(billing.repo.get().unwrap().0, "billing");
+    assert_eq!(
+        container.resolve::<Repo>("billing::repo").unwrap().0,
+        "billing"
+    );
+}
+
+#[test]
+fn module_providers_fall_back_to_global_keys() {
+    let container = app().build();
+    assert!(container.resolve::<Service>("auth::service").is_ok());
+}
+
+#[test]
+fn internal_keys_are_hidden_outside_of_their_module() {
+    let container = app().build();
+
+    match container.resolve::<Repo>("auth::repo") {
+        Err(Error::KeyNotFound { key, .. }) => assert_eq!(key, "auth::repo"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("auth::repo should not be exported"),
+    }
+    assert!(container
+        .try_resolve::<Repo>("auth::repo")
+        .unwrap()
+        .is_none());
+    assert!(container.resolve::<Repo>("repo").is_err());
+
+    // Still hidden once it has been resolved by its own module.
+    let service = container.resolve::<Service>("auth::service").unwrap();
+    assert_eq!(service.repo.0, "auth");
+    assert!(container.scoped().resolve::<Repo>("auth::repo").is_err());
+}
+
+#[test]
+fn missing_module_dependencies_fail_the_build() {
+    let result = ContainerBuilder::new()
+        .register("config", ConfigProvider)
+        .add_module(BillingModule)
+        .try_build();
+
+    match result {
+        Err(Error::MissingModules(missing)) => {
+            assert_eq!(missing, [("billing".to_owned(), "auth".to_owned())])
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected the auth module to be missing"),
+    }
+}
+
+#[test]
+fn module_keys_can_be_overridden() {
+    let container = app()
+        .override_with(
+            "auth::repo",
+            Registration::new(RegistrationKind::Singleton, BillingRepoProvider),
+        )
+        .unwrap()
+        .build();
+
+    let service = container.resolve::<Service>("auth::service").unwrap();
+    assert_eq!(service.repo.0, "billing");
+    assert!(container.resolve::<Repo>("auth::repo").is_err());
+}
+
+#[test]
+fn modules_cannot_register_keys_of_other_modules() {
+    struct IntruderModule;
+
+    impl Module for IntruderModule {
+        fn name(&self) -> &'static str {
+            "intruder"
+        }
+
+        fn exports(&self) -> &'static [&'static str] {
+            &[]
+        }
+
+        fn register(&self, builder: ContainerBuilder) -> ContainerBuilder {
+            builder.register("billing::repo", RepoProvider)
+        }
+    }
+
+    let result = app().add_module(IntruderModule).try_build();
+
+    match result {
+        Err(Error::ForeignModuleKeys(keys)) => {
+            assert_eq!(keys, [("intruder".to_owned(), "billing::repo".to_owned())])
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected billing::repo to be rejected"),
+    }
+}
+
+#[test]
+fn exports_must_be_registered() {
+    struct ReportsModule;
+
+    impl Module for ReportsModule {
+        fn name(&self) -> &'static str {
+            "reports"
+        }
+
+        fn exports(&self) -> &'static [&'static str] {
+            &["repo", "reports"]
+        }
+
+        fn register(&self, builder: ContainerBuilder) -> ContainerBuilder {
+            builder.register("repo", RepoProvider)
+        }
+    }
+
+    let result = app().add_module(ReportsModule).try_build();
+
+    match result {
+        Err(Error::UnknownExports(unknown)) => {
+            assert_eq!(unknown, [("reports".to_owned(), "reports".to_owned())])
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected reports to be unknown"),
+    }
+}
+
+#[test]
+fn qualified_keys_work_outside_of_modules() {
+    let container = ContainerBuilder::new()
+        .register("db::pool", ConfigProvider)
+        .build();
+
+    assert!(container.resolve::<Config>("db::pool").is_ok());
+}
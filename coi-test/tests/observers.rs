@@ -0,0 +1,225 @@
+use coi::{
+    Container, ContainerBuilder, Error, Inject, Registration, RegistrationKind, ResolveObserver,
+};
+use futures::executor::block_on;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Records every event it's notified of, along with its name.
+#[derive(Default)]
+struct Recorder {
+    name: &'static str,
+    events: Mutex<Vec<String>>,
+    durations: Mutex<Vec<Duration>>,
+}
+
+impl Recorder {
+    fn named(name: &'static str) -> Arc<Self> {
+        Arc::new(Recorder {
+            name,
+            ..Recorder::default()
+        })
+    }
+
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    fn record(&self, event: String) {
+        let event = if self.name.is_empty() {
+            event
+        } else {
+            format!("{}: {}", self.name, event)
+        };
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl ResolveObserver for Recorder {
+    fn resolve_started(&self, key: &str) {
+        self.record(format!("started {}", key));
+    }
+
+    fn cache_hit(&self, key: &str, kind: RegistrationKind) {
+        self.record(format!("hit {} {:?}", key, kind));
+    }
+
+    fn provider_finished(&self, key: &str, kind: RegistrationKind, duration: Duration) {
+        self.durations.lock().unwrap().push(duration);
+        self.record(format!("provided {} {:?}", key, kind));
+    }
+
+    fn resolve_failed(&self, key: &str, _: &Error) {
+        self.record(format!("failed {}", key));
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides Config with Config)]
+struct Config;
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides Server with Server { config })]
+struct Server {
+    #[coi(inject)]
+    config: Arc<Config>,
+}
+
+#[derive(Inject)]
+#[coi(provides Pool with async Pool::connect())]
+struct Pool;
+
+impl Pool {
+    async fn connect() -> Self {
+        Pool
+    }
+}
+
+fn container(recorder: &Arc<Recorder>) -> Container {
+    ContainerBuilder::new()
+        .register_as(
+            "config",
+            Registration::new(RegistrationKind::Singleton, ConfigProvider),
+        )
+        .register_as(
+            "server",
+            Registration::new(RegistrationKind::Scoped, ServerProvider),
+        )
+        .add_observer(Arc::clone(recorder))
+        .build()
+}
+
+#[test]
+fn constructions_and_cache_hits_are_observed() {
+    let recorder = Recorder::named("");
+    let container = container(&recorder);
+
+    let scoped = container.scoped();
+    scoped.resolve::<Server>("server").unwrap();
+    assert_eq!(
+        recorder.take(),
+        [
+            "started server",
+            "started config",
+            "provided config Singleton",
+            "provided server Scoped",
+        ]
+    );
+
+    scoped.resolve::<Server>("server").unwrap();
+    assert_eq!(recorder.take(), ["started server", "hit server Scoped"]);
+
+    container.scoped().resolve::<Server>("server").unwrap();
+    assert_eq!(
+        recorder.take(),
+        [
+            "started server",
+            "started config",
+            "hit config Singleton",
+            "provided server Scoped",
+        ]
+    );
+}
+
+#[test]
+fn failures_are_observed_along_the_resolution_path() {
+    let recorder = Recorder::named("");
+    let container = ContainerBuilder::new()
+        .register("server", ServerProvider)
+        .add_observer(Arc::clone(&recorder))
+        .build();
+
+    assert!(container.resolve::<Server>("server").is_err());
+    assert_eq!(
+        recorder.take(),
+        [
+            "started server",
+            "started config",
+            "failed config",
+            "failed server",
+        ]
+    );
+}
+
+#[test]
+fn provider_durations_are_observed() {
+    let recorder = Recorder::named("");
+    let container = ContainerBuilder::new()
+        .register("slow", |_: &Container| {
+            thread::sleep(Duration::from_millis(20));
+            Ok(Arc::new(Config))
+        })
+        .add_observer(Arc::clone(&recorder))
+        .build();
+
+    container.resolve::<Config>("slow").unwrap();
+    let durations = recorder.durations.lock().unwrap();
+    assert_eq!(durations.len(), 1);
+    assert!(durations[0] >= Duration::from_millis(20));
+}
+
+#[test]
+fn async_resolutions_are_observed() {
+    let recorder = Recorder::named("");
+    let container = ContainerBuilder::new()
+        .register_async_as(
+            "pool",
+            Registration::new(RegistrationKind::Singleton, PoolProvider),
+        )
+        .register("config", ConfigProvider)
+        .add_observer(Arc::clone(&recorder))
+        .build();
+
+    block_on(container.resolve_async::<Pool>("pool")).unwrap();
+    block_on(container.resolve_async::<Pool>("pool")).unwrap();
+    block_on(container.resolve_async::<Config>("config")).unwrap();
+    assert_eq!(
+        recorder.take(),
+        [
+            "started pool",
+            "provided pool Singleton",
+            "started pool",
+            "hit pool Singleton",
+            "started config",
+            "provided config Transient",
+        ]
+    );
+}
+
+#[test]
+fn derived_containers_keep_their_parents_observers() {
+    let parent = Recorder::named("parent");
+    let child = Recorder::named("child");
+    let container = ContainerBuilder::new()
+        .register_as(
+            "config",
+            Registration::new(RegistrationKind::Singleton, ConfigProvider),
+        )
+        .add_observer(Arc::clone(&parent))
+        .build();
+    let scoped = container.scoped_with(|builder| {
+        builder
+            .register_as(
+                "server",
+                Registration::new(RegistrationKind::Scoped, ServerProvider),
+            )
+            .add_observer(Arc::clone(&child))
+    });
+
+    scoped.resolve::<Server>("server").unwrap();
+    assert_eq!(
+        parent.take(),
+        [
+            "parent: started server",
+            "parent: started config",
+            "parent: provided config Singleton",
+            "parent: provided server Scoped",
+        ]
+    );
+    assert_eq!(
+        child.take(),
+        ["child: started server", "child: provided server Scoped"]
+    );
+}
@@ -0,0 +1,153 @@
+use coi::{container, ContainerBuilder, Error, Inject};
+use futures::executor::block_on;
+use std::sync::Arc;
+
+trait Metrics: Inject {
+    fn sink(&self) -> &'static str;
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Metrics with StatsdMetrics)]
+struct StatsdMetrics;
+
+impl Metrics for StatsdMetrics {
+    fn sink(&self) -> &'static str {
+        "statsd"
+    }
+}
+
+trait Db: Inject {}
+
+#[allow(dead_code)]
+#[derive(Inject)]
+#[coi(provides dyn Metrics with BrokenMetrics { db })]
+struct BrokenMetrics {
+    #[coi(inject)]
+    db: Arc<dyn Db>,
+}
+
+impl Metrics for BrokenMetrics {
+    fn sink(&self) -> &'static str {
+        "broken"
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides Service with Service { metrics })]
+struct Service {
+    #[coi(inject)]
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+#[derive(Inject)]
+#[coi(provides TypedService with TypedService(_0))]
+struct TypedService(#[coi(inject)] Option<Arc<dyn Metrics>>);
+
+#[derive(Inject)]
+#[coi(provides AsyncService with async AsyncService::new(metrics))]
+struct AsyncService {
+    #[coi(inject)]
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl AsyncService {
+    async fn new(metrics: Option<Arc<dyn Metrics>>) -> Self {
+        Self { metrics }
+    }
+}
+
+#[test]
+fn try_resolve_returns_none_when_not_registered() {
+    let container = ContainerBuilder::new().build();
+    assert!(container
+        .try_resolve::<dyn Metrics>("metrics")
+        .unwrap()
+        .is_none());
+    assert!(container
+        .try_resolve_type::<dyn Metrics>()
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn try_resolve_returns_registered_provider() {
+    let container = container! {
+        metrics => StatsdMetricsProvider,
+    };
+    let metrics = container.try_resolve::<dyn Metrics>("metrics").unwrap();
+    assert_eq!(metrics.unwrap().sink(), "statsd");
+}
+
+#[test]
+fn try_resolve_searches_parent_containers() {
+    let container = container! {
+        metrics => StatsdMetricsProvider; singleton,
+    };
+    let scoped = container.scoped();
+    assert!(scoped
+        .try_resolve::<dyn Metrics>("metrics")
+        .unwrap()
+        .is_some());
+}
+
+#[test]
+fn try_resolve_reports_provider_failures() {
+    let container = container! {
+        metrics => BrokenMetricsProvider,
+    };
+    match container.try_resolve::<dyn Metrics>("metrics") {
+        Err(Error::KeyNotFound { key, .. }) => assert_eq!(key, "db"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn derive_injects_optional_dependencies() {
+    let without = container! {
+        service => ServiceProvider,
+    };
+    let service = without.resolve::<Service>("service").unwrap();
+    assert!(service.metrics.is_none());
+
+    let with = container! {
+        metrics => StatsdMetricsProvider,
+        service => ServiceProvider,
+    };
+    let service = with.resolve::<Service>("service").unwrap();
+    assert_eq!(service.metrics.as_ref().unwrap().sink(), "statsd");
+}
+
+#[test]
+fn derive_injects_optional_type_keyed_dependencies() {
+    let container = ContainerBuilder::new()
+        .register_type::<TypedService, _>(TypedServiceProvider)
+        .build();
+    let service = container.resolve_type::<TypedService>().unwrap();
+    assert!(service.0.is_none());
+
+    let container = ContainerBuilder::new()
+        .register_type::<dyn Metrics, _>(StatsdMetricsProvider)
+        .register_type::<TypedService, _>(TypedServiceProvider)
+        .build();
+    let service = container.resolve_type::<TypedService>().unwrap();
+    assert_eq!(service.0.as_ref().unwrap().sink(), "statsd");
+}
+
+#[test]
+fn derive_injects_optional_dependencies_asynchronously() {
+    let container = ContainerBuilder::new()
+        .register_async("service", AsyncServiceProvider)
+        .build();
+    let service = block_on(container.resolve_async::<AsyncService>("service")).unwrap();
+    assert!(service.metrics.is_none());
+
+    let container = ContainerBuilder::new()
+        .register("metrics", StatsdMetricsProvider)
+        .register_async("service", AsyncServiceProvider)
+        .build();
+    let service = block_on(container.try_resolve_async::<AsyncService>("service"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(service.metrics.as_ref().unwrap().sink(), "statsd");
+}
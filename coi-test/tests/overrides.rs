@@ -0,0 +1,234 @@
+use coi::{container, Container, ContainerBuilder, Error, Inject, Registration, RegistrationKind};
+use std::sync::Arc;
+
+trait Db: Inject {
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Db with Postgres)]
+struct Postgres;
+
+impl Db for Postgres {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Db with InMemory)]
+struct InMemory;
+
+impl Db for InMemory {
+    fn name(&self) -> &'static str {
+        "in memory"
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides Repo with Repo { db })]
+struct Repo {
+    #[coi(inject)]
+    db: Arc<dyn Db>,
+}
+
+#[derive(Inject)]
+#[coi(provides Service with Service { repo })]
+struct Service {
+    #[coi(inject)]
+    repo: Arc<Repo>,
+}
+
+fn production() -> ContainerBuilder {
+    ContainerBuilder::new()
+        .register_as(
+            "db",
+            Registration::new(RegistrationKind::Singleton, PostgresProvider),
+        )
+        .register("repo", RepoProvider)
+}
+
+#[test]
+fn override_with_replaces_registration() {
+    let container = production()
+        .override_with(
+            "db",
+            Registration::new(RegistrationKind::Singleton, InMemoryProvider),
+        )
+        .unwrap()
+        .build();
+
+    assert_eq!(
+        container.resolve::<dyn Db>("db").unwrap().name(),
+        "in memory"
+    );
+    let repo = container.resolve::<Repo>("repo").unwrap();
+    assert_eq!(repo.db.name(), "in memory");
+}
+
+#[test]
+fn override_with_unregistered_key_errors() {
+    let result = production().override_with(
+        "cache",
+        Registration::new(RegistrationKind::Singleton, InMemoryProvider),
+    );
+    match result {
+        Err(Error::KeyNotFound { key, .. }) => assert_eq!(key, "cache"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn override_type_with_replaces_type_registration() {
+    let container = ContainerBuilder::new()
+        .register_type::<dyn Db, _>(PostgresProvider)
+        .override_type_with::<dyn Db, _>(Registration::new(
+            RegistrationKind::Transient,
+            InMemoryProvider,
+        ))
+        .unwrap()
+        .build();
+
+    assert_eq!(
+        container.resolve_type::<dyn Db>().unwrap().name(),
+        "in memory"
+    );
+    assert!(ContainerBuilder::new()
+        .override_type_with::<dyn Db, _>(Registration::new(
+            RegistrationKind::Transient,
+            InMemoryProvider
+        ))
+        .is_err());
+}
+
+#[test]
+fn with_overrides_shadows_resolved_singletons() {
+    let container = production().build();
+    let db = container.resolve::<dyn Db>("db").unwrap();
+    assert_eq!(db.name(), "postgres");
+
+    let overridden = container.with_overrides(|builder| {
+        builder.register_as(
+            "db",
+            Registration::new(RegistrationKind::Singleton, InMemoryProvider),
+        )
+    });
+    let fake = overridden.resolve::<dyn Db>("db").unwrap();
+    assert_eq!(fake.name(), "in memory");
+    assert!(Arc::ptr_eq(
+        &fake,
+        &overridden.resolve::<dyn Db>("db").unwrap()
+    ));
+
+    // Transient dependents are constructed by the child, so they see the override.
+    let repo = overridden.resolve::<Repo>("repo").unwrap();
+    assert!(Arc::ptr_eq(&repo.db, &fake));
+
+    // The parent is left untouched.
+    assert!(Arc::ptr_eq(
+        &db,
+        &container.resolve::<dyn Db>("db").unwrap()
+    ));
+    let repo = container.resolve::<Repo>("repo").unwrap();
+    assert_eq!(repo.db.name(), "postgres");
+}
+
+#[test]
+fn with_overrides_reconstructs_singletons_that_depend_on_overrides() {
+    let container = container! {
+        db => PostgresProvider; singleton,
+        repo => RepoProvider; singleton,
+        service => ServiceProvider; singleton,
+        unrelated => PostgresProvider; singleton,
+    };
+    let repo = container.resolve::<Repo>("repo").unwrap();
+    let service = container.resolve::<Service>("service").unwrap();
+    let unrelated = container.resolve::<dyn Db>("unrelated").unwrap();
+
+    let overridden = container.with_overrides(|builder| {
+        builder.register("db", |_: &Container| Ok(Arc::new(InMemory) as Arc<dyn Db>))
+    });
+    let fake_repo = overridden.resolve::<Repo>("repo").unwrap();
+    assert_eq!(fake_repo.db.name(), "in memory");
+    assert!(Arc::ptr_eq(
+        &fake_repo,
+        &overridden.resolve::<Repo>("repo").unwrap()
+    ));
+    // Dependents of dependents are constructed again too.
+    let fake_service = overridden.resolve::<Service>("service").unwrap();
+    assert!(Arc::ptr_eq(&fake_service.repo, &fake_repo));
+    // Singletons that don't depend on the overrides are still shared.
+    assert!(Arc::ptr_eq(
+        &unrelated,
+        &overridden.resolve::<dyn Db>("unrelated").unwrap()
+    ));
+
+    // The parent is left untouched.
+    assert!(Arc::ptr_eq(
+        &repo,
+        &container.resolve::<Repo>("repo").unwrap()
+    ));
+    assert!(Arc::ptr_eq(
+        &service,
+        &container.resolve::<Service>("service").unwrap()
+    ));
+    assert_eq!(repo.db.name(), "postgres");
+}
+
+#[test]
+fn with_overrides_shares_singletons_with_unknown_dependencies() {
+    let container = container! {
+        db => PostgresProvider; singleton,
+        repo => |c: &Container| Ok(Arc::new(Repo { db: c.resolve("db")? })); singleton,
+    };
+    let repo = container.resolve::<Repo>("repo").unwrap();
+
+    let overridden = container.with_overrides(|builder| builder.register("db", InMemoryProvider));
+    assert!(Arc::ptr_eq(
+        &repo,
+        &overridden.resolve::<Repo>("repo").unwrap()
+    ));
+}
+
+#[test]
+fn with_overrides_appends_to_collections() {
+    let container = ContainerBuilder::new()
+        .register_all("dbs", PostgresProvider)
+        .build();
+
+    let overridden =
+        container.with_overrides(|builder| builder.register_all("dbs", InMemoryProvider));
+    let names: Vec<_> = overridden
+        .resolve_all::<dyn Db>("dbs")
+        .unwrap()
+        .iter()
+        .map(|db| db.name())
+        .collect();
+    assert_eq!(names, ["postgres", "in memory"]);
+    assert_eq!(container.resolve_all::<dyn Db>("dbs").unwrap().len(), 1);
+}
+
+#[test]
+fn scopes_of_an_overridden_container_share_its_singletons() {
+    let container = production().build();
+    let overridden = container.with_overrides(|builder| {
+        builder.register_as(
+            "db",
+            Registration::new(RegistrationKind::Singleton, InMemoryProvider),
+        )
+    });
+
+    let first = overridden.scoped().resolve::<dyn Db>("db").unwrap();
+    let second = overridden
+        .scoped()
+        .scoped()
+        .resolve::<dyn Db>("db")
+        .unwrap();
+    assert_eq!(first.name(), "in memory");
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(Arc::ptr_eq(
+        &first,
+        &overridden.resolve::<dyn Db>("db").unwrap()
+    ));
+}
@@ -0,0 +1,171 @@
+use coi::{
+    AsyncProvide, Container, ContainerBuilder, Error, Inject, Provide, Registration,
+    RegistrationKind,
+};
+use futures::{executor::block_on, future::BoxFuture};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::thread;
+
+#[derive(Debug)]
+struct Flaky;
+
+impl Inject for Flaky {}
+
+/// Panics the first `panics` times it's asked to provide.
+struct FlakyProvider {
+    panics: usize,
+    calls: AtomicUsize,
+}
+
+impl FlakyProvider {
+    fn new(panics: usize) -> Self {
+        Self {
+            panics,
+            calls: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Provide for FlakyProvider {
+    type Output = Flaky;
+
+    fn provide(&self, _: &Container) -> coi::Result<Arc<Flaky>> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        if call < self.panics {
+            panic!("flaky call {}", call);
+        }
+        Ok(Arc::new(Flaky))
+    }
+}
+
+impl AsyncProvide for FlakyProvider {
+    type Output = Flaky;
+
+    fn provide<'a>(&'a self, container: &'a Container) -> BoxFuture<'a, coi::Result<Arc<Flaky>>> {
+        Box::pin(async move { Provide::provide(self, container) })
+    }
+}
+
+#[derive(Debug)]
+struct Dependent(#[allow(dead_code)] Arc<Flaky>);
+
+impl Inject for Dependent {}
+
+fn assert_panicked<T: std::fmt::Debug>(result: coi::Result<T>, expected_key: &str, expected: &str) {
+    match result {
+        Err(Error::ProviderPanicked { key, message }) => {
+            assert_eq!(key, expected_key);
+            assert_eq!(message, expected);
+        }
+        other => panic!("expected a panic to be caught, got {:?}", other),
+    }
+}
+
+#[test]
+fn provider_panic_is_returned_as_error() {
+    let container = ContainerBuilder::new()
+        .register_as(
+            "flaky",
+            Registration::new(RegistrationKind::Singleton, FlakyProvider::new(1)),
+        )
+        .build();
+
+    assert_panicked(container.resolve::<Flaky>("flaky"), "flaky", "flaky call 0");
+    let first = container.resolve::<Flaky>("flaky").unwrap();
+    let second = container.resolve::<Flaky>("flaky").unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+}
+
+#[test]
+fn str_panic_messages_are_kept() {
+    let container = ContainerBuilder::new()
+        .register("flaky", |_: &Container| -> coi::Result<Arc<Flaky>> {
+            panic!("static message")
+        })
+        .build();
+
+    assert_panicked(
+        container.resolve::<Flaky>("flaky"),
+        "flaky",
+        "static message",
+    );
+}
+
+#[test]
+fn dependency_panic_fails_the_dependent() {
+    let container = ContainerBuilder::new()
+        .register_as(
+            "flaky",
+            Registration::new(RegistrationKind::Singleton, FlakyProvider::new(1)),
+        )
+        .register("dependent", |container: &Container| {
+            let flaky = container.resolve::<Flaky>("flaky")?;
+            Ok(Arc::new(Dependent(flaky)))
+        })
+        .build();
+
+    assert_panicked(
+        container.resolve::<Dependent>("dependent"),
+        "flaky",
+        "flaky call 0",
+    );
+    assert!(container.resolve::<Dependent>("dependent").is_ok());
+}
+
+#[test]
+fn scoped_container_is_usable_after_panic() {
+    let container = ContainerBuilder::new()
+        .register_as(
+            "flaky",
+            Registration::new(RegistrationKind::Scoped, FlakyProvider::new(1)),
+        )
+        .build();
+
+    let scoped = container.scoped();
+    assert_panicked(scoped.resolve::<Flaky>("flaky"), "flaky", "flaky call 0");
+    assert!(scoped.resolve::<Flaky>("flaky").is_ok());
+    assert!(container.scoped().resolve::<Flaky>("flaky").is_ok());
+}
+
+#[test]
+fn concurrent_resolution_survives_panics() {
+    const THREADS: usize = 8;
+    let container = ContainerBuilder::new()
+        .register_as(
+            "flaky",
+            Registration::new(RegistrationKind::Singleton, FlakyProvider::new(THREADS / 2)),
+        )
+        .build();
+
+    let panicked: usize = thread::scope(|s| {
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| s.spawn(|| container.resolve::<Flaky>("flaky").is_err()))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap() as usize)
+            .sum()
+    });
+    assert_eq!(panicked, THREADS / 2);
+    assert!(container.resolve::<Flaky>("flaky").is_ok());
+}
+
+#[test]
+fn async_provider_panic_is_returned_as_error() {
+    let container = ContainerBuilder::new()
+        .register_async_as(
+            "flaky",
+            Registration::new(RegistrationKind::Singleton, FlakyProvider::new(1)),
+        )
+        .build();
+
+    assert_panicked(
+        block_on(container.resolve_async::<Flaky>("flaky")),
+        "flaky",
+        "flaky call 0",
+    );
+    assert!(block_on(container.resolve_async::<Flaky>("flaky")).is_ok());
+}
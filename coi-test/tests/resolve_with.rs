@@ -0,0 +1,207 @@
+use coi::{
+    Container, ContainerBuilder, Error, Inject, ProvideWith, Registration, RegistrationKind,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Inject)]
+#[coi(provides Db with Db)]
+struct Db;
+
+#[derive(Inject)]
+#[coi(provides Session with Session { db, user_id })]
+struct Session {
+    #[coi(inject)]
+    db: Arc<Db>,
+    #[coi(arg)]
+    user_id: u64,
+}
+
+#[derive(Inject)]
+#[coi(provides Export with Export { db, user_id, path })]
+struct Export {
+    #[coi(inject)]
+    db: Arc<Db>,
+    #[coi(arg)]
+    user_id: u64,
+    #[coi(arg)]
+    path: PathBuf,
+}
+
+#[derive(Inject)]
+#[coi(provides Upload with Upload(_0, db))]
+struct Upload(#[coi(arg)] String, #[coi(inject = "db")] Arc<Db>);
+
+#[derive(Inject)]
+#[coi(provides Mount with Mount { db, container })]
+struct Mount {
+    #[coi(inject)]
+    db: Arc<Db>,
+    #[coi(arg)]
+    container: String,
+}
+
+fn container() -> Container {
+    ContainerBuilder::new()
+        .register_as(
+            "db",
+            Registration::new(RegistrationKind::Singleton, DbProvider),
+        )
+        .register_with("session", SessionProvider)
+        .register_with("export", ExportProvider)
+        .register_with("upload", UploadProvider)
+        .register_with("mount", MountProvider)
+        .build()
+}
+
+#[test]
+fn derived_provider_takes_a_single_argument() {
+    let container = container();
+    let session = container
+        .resolve_with::<Session, u64>("session", 7)
+        .unwrap();
+    assert_eq!(session.user_id, 7);
+    assert!(Arc::ptr_eq(
+        &session.db,
+        &container.resolve::<Db>("db").unwrap()
+    ));
+}
+
+#[test]
+fn derived_provider_takes_arguments_as_a_tuple_in_field_order() {
+    let container = container();
+    let export = container
+        .resolve_with::<Export, (u64, PathBuf)>("export", (7, PathBuf::from("out.csv")))
+        .unwrap();
+    assert_eq!(export.user_id, 7);
+    assert_eq!(export.path, PathBuf::from("out.csv"));
+    assert!(Arc::ptr_eq(
+        &export.db,
+        &container.resolve::<Db>("db").unwrap()
+    ));
+
+    let upload = container
+        .resolve_with::<Upload, String>("upload", "file".to_owned())
+        .unwrap();
+    assert_eq!(upload.0, "file");
+    assert!(Arc::ptr_eq(&upload.1, &export.db));
+}
+
+#[test]
+fn every_resolution_constructs_a_new_instance() {
+    let container = container();
+    let first = container
+        .resolve_with::<Session, u64>("session", 1)
+        .unwrap();
+    let second = container
+        .resolve_with::<Session, u64>("session", 2)
+        .unwrap();
+    assert_eq!((first.user_id, second.user_id), (1, 2));
+    assert!(Arc::ptr_eq(&first.db, &second.db));
+}
+
+#[test]
+fn scopes_resolve_with_arguments() {
+    let container = container();
+    let scoped = container.scoped();
+    let session = scoped.resolve_with::<Session, u64>("session", 3).unwrap();
+    assert_eq!(session.user_id, 3);
+}
+
+#[test]
+fn handwritten_provider_is_resolved_with_arguments() {
+    struct Greeting(String);
+
+    impl Inject for Greeting {}
+
+    struct GreetingProvider;
+
+    impl ProvideWith<&'static str> for GreetingProvider {
+        type Output = Greeting;
+
+        fn provide_with(&self, _: &Container, name: &'static str) -> coi::Result<Arc<Greeting>> {
+            Ok(Arc::new(Greeting(format!("hello {}", name))))
+        }
+    }
+
+    let container = ContainerBuilder::new()
+        .register_with("greeting", GreetingProvider)
+        .build();
+    let greeting = container
+        .resolve_with::<Greeting, &'static str>("greeting", "world")
+        .unwrap();
+    assert_eq!(greeting.0, "hello world");
+}
+
+#[test]
+fn resolving_without_arguments_fails() {
+    let container = container();
+    match container.resolve::<Session>("session") {
+        Err(Error::RequiresArguments(key)) => assert_eq!(key, "session"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn resolving_with_the_wrong_arguments_is_a_type_mismatch() {
+    let container = container();
+    match container.resolve_with::<Session, u32>("session", 7) {
+        Err(Error::TypeMismatch { key, .. }) => assert_eq!(key, "session"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+    match container.resolve_with::<Db, u64>("db", 7) {
+        Err(Error::TypeMismatch { key, .. }) => assert_eq!(key, "db"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn missing_dependencies_are_reported_with_their_path() {
+    let container = ContainerBuilder::new()
+        .register_with("session", SessionProvider)
+        .build();
+    match container.resolve_with::<Session, u64>("session", 7) {
+        Err(Error::KeyNotFound { key, path, .. }) => {
+            assert_eq!(key, "db");
+            assert_eq!(path, ["session", "db"]);
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn derived_factories_take_each_argument() {
+    let container = container();
+    let export = ExportFactory::new("export")
+        .create(&container, 7, PathBuf::from("out.csv"))
+        .unwrap();
+    assert_eq!(export.user_id, 7);
+    assert_eq!(export.path, PathBuf::from("out.csv"));
+
+    let session = SessionFactory::new("session")
+        .create(&container, 3)
+        .unwrap();
+    assert_eq!(session.user_id, 3);
+
+    let upload = UploadFactory::new("upload")
+        .create(&container.scoped(), "file".to_owned())
+        .unwrap();
+    assert_eq!(upload.0, "file");
+}
+
+#[test]
+fn arguments_can_be_named_container() {
+    let container = container();
+    let mount = MountFactory::new("mount")
+        .create(&container, "uploads".to_owned())
+        .unwrap();
+    assert_eq!(mount.container, "uploads");
+    assert!(Arc::ptr_eq(
+        &mount.db,
+        &container.resolve::<Db>("db").unwrap()
+    ));
+}
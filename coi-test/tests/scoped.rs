@@ -216,3 +216,61 @@ fn scoped_registration_provides_same_instance_regardless_of_nesting_order() {
         "If the ids are different, they were resolved in different scopes!"
     );
 }
+
+struct User(&'static str);
+
+impl Inject for User {}
+
+#[derive(Inject)]
+#[coi(provides Session with Session { user })]
+struct Session {
+    #[coi(inject)]
+    user: Arc<User>,
+}
+
+#[test]
+fn scoped_with_registrations_are_visible_to_scoped_registrations() {
+    let container = container! {
+        session => SessionProvider; scoped,
+    };
+
+    let alice =
+        container.scoped_with(|builder| builder.register_instance("user", Arc::new(User("alice"))));
+    let bob = container
+        .scoped_with(|builder| builder.register("user", |_: &Container| Ok(Arc::new(User("bob")))));
+
+    assert_eq!(alice.resolve::<Session>("session").unwrap().user.0, "alice");
+    assert_eq!(bob.resolve::<Session>("session").unwrap().user.0, "bob");
+    assert!(container.resolve::<Session>("session").is_err());
+    assert!(container.resolve::<User>("user").is_err());
+}
+
+#[test]
+fn scoped_with_registrations_are_visible_to_nested_scopes() {
+    let container = container! {
+        session => SessionProvider; scoped,
+    };
+
+    let scope =
+        container.scoped_with(|builder| builder.register_instance("user", Arc::new(User("alice"))));
+    let nested = scope.scoped();
+    assert_eq!(
+        nested.resolve::<Session>("session").unwrap().user.0,
+        "alice"
+    );
+    assert!(Arc::ptr_eq(
+        &nested.resolve::<User>("user").unwrap(),
+        &scope.resolve::<User>("user").unwrap()
+    ));
+}
+
+#[test]
+fn scoped_with_registrations_are_not_visible_to_singletons() {
+    let container = container! {
+        session => SessionProvider; singleton,
+    };
+
+    let scope =
+        container.scoped_with(|builder| builder.register_instance("user", Arc::new(User("alice"))));
+    assert!(scope.resolve::<Session>("session").is_err());
+}
@@ -0,0 +1,118 @@
+use coi::{ContainerBuilder, Error, Inject, Registration, RegistrationKind};
+use futures::executor::block_on;
+use std::{ops::Deref, sync::Arc};
+
+trait Repo: Inject {
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Repo with SqlRepo)]
+struct SqlRepo;
+
+impl Repo for SqlRepo {
+    fn name(&self) -> &'static str {
+        "sql"
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides dyn Repo with MemoryRepo)]
+struct MemoryRepo;
+
+impl Repo for MemoryRepo {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
+
+#[derive(Inject)]
+#[coi(provides Service with Service(_0))]
+struct Service(#[coi(inject)] Arc<dyn Repo>);
+
+#[derive(Inject)]
+#[coi(provides Mixed with Mixed(_0, memory))]
+struct Mixed(
+    #[coi(inject)] Arc<dyn Repo>,
+    #[coi(inject = "memory")] Arc<dyn Repo>,
+);
+
+#[derive(Inject)]
+#[coi(provides AsyncService with async AsyncService::new(_0))]
+struct AsyncService(#[coi(inject)] Arc<dyn Repo>);
+
+impl AsyncService {
+    async fn new(repo: Arc<dyn Repo>) -> Self {
+        Self(repo)
+    }
+}
+
+#[test]
+fn type_keys_resolve_alongside_string_keys() {
+    let container = ContainerBuilder::new()
+        .register_type::<dyn Repo, _>(SqlRepoProvider)
+        .register("memory", MemoryRepoProvider)
+        .register_type::<Service, _>(ServiceProvider)
+        .register_type::<Mixed, _>(MixedProvider)
+        .build();
+
+    assert_eq!(container.resolve_type::<dyn Repo>().unwrap().name(), "sql");
+    assert_eq!(
+        container.resolve::<dyn Repo>("memory").unwrap().name(),
+        "memory"
+    );
+    assert_eq!(container.resolve_type::<Service>().unwrap().0.name(), "sql");
+
+    let mixed = container.resolve_type::<Mixed>().unwrap();
+    assert_eq!(mixed.0.name(), "sql");
+    assert_eq!(mixed.1.name(), "memory");
+}
+
+#[test]
+fn type_keys_respect_registration_kind() {
+    let container = ContainerBuilder::new()
+        .register_type_as::<dyn Repo, _>(Registration::new(
+            RegistrationKind::Scoped,
+            SqlRepoProvider,
+        ))
+        .build();
+    let scoped = container.scoped();
+
+    let a = container.resolve_type::<dyn Repo>().unwrap();
+    let b = container.resolve_type::<dyn Repo>().unwrap();
+    let c = scoped.resolve_type::<dyn Repo>().unwrap();
+    assert_eq!(
+        a.deref() as &dyn Repo as *const _,
+        b.deref() as &dyn Repo as *const _
+    );
+    assert_ne!(
+        a.deref() as &dyn Repo as *const _,
+        c.deref() as &dyn Repo as *const _
+    );
+}
+
+#[test]
+fn missing_type_key_reports_type_name() {
+    let container = ContainerBuilder::new()
+        .register_type::<Service, _>(ServiceProvider)
+        .build();
+    match container.resolve_type::<Service>() {
+        Err(Error::KeyNotFound { key, path, .. }) => {
+            let repo = std::any::type_name::<dyn Repo>();
+            assert_eq!(key, repo);
+            assert_eq!(path, [std::any::type_name::<Service>(), repo]);
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("dyn Repo was never registered"),
+    }
+}
+
+#[test]
+fn type_keys_resolve_async() {
+    let container = ContainerBuilder::new()
+        .register_type::<dyn Repo, _>(MemoryRepoProvider)
+        .register_async_type::<AsyncService, _>(AsyncServiceProvider)
+        .build();
+    let service = block_on(container.resolve_type_async::<AsyncService>()).unwrap();
+    assert_eq!(service.0.name(), "memory");
+}
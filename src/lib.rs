@@ -4,13 +4,13 @@
 //! Currently, this crate provides the following:
 //! - **[`coi::Inject` (trait)]** - a marker trait that indicates a trait or struct is injectable.
 //! - **[`coi::Provide` (trait)]** - a trait that indicates a struct is capable of providing a specific
-//! implementation of some injectable trait. This is generated for you if you use
-//! [`coi::Inject` (derive)] or [`coi::Provide` (derive)], but can also be written manually.
+//!   implementation of some injectable trait. This is generated for you if you use
+//!   [`coi::Inject` (derive)] or [`coi::Provide` (derive)], but can also be written manually.
 //! - **[`coi::Container`]** - a container to manage the lifetime of all dependencies. This is still
-//! in its early stages, and currently only supports objects that are recreated with each request to
-//! [`coi::Container::resolve`].
+//!   in its early stages, and currently only supports objects that are recreated with each request to
+//!   [`coi::Container::resolve`].
 //! - **[`coi::ContainerBuilder`]** - a builder for the above container to simplify construction and
-//! guarantee immutability after construction.
+//!   guarantee immutability after construction.
 //!
 //! [`coi::Inject` (trait)]: trait.Inject.html
 //! [`coi::Inject` (derive)]: derive.Inject.html
@@ -94,11 +94,9 @@
 //! // the trait it provides. This crate currently only supports `Arc<dyn Trait>`, but this may
 //! // be expanded in a future version of the crate.
 //! let trait2 = container
-//!     // Note: Getting the key wrong will produce an error telling you which key in the
-//!     // chain of dependencies caused the failure (future versions might provider a vec of
-//!     // chain that lead to the failure). Getting the type wrong will only tell you which key
-//!     // had the wrong type. This is because at runtime, we do not have any type information,
-//!     // only unique ids (that change during each compilation).
+//!     // Note: Getting the key or the type wrong will produce an error telling you which key
+//!     // caused the failure, the type it was requested as, and the chain of dependencies that
+//!     // lead to it, e.g. `trait2 -> trait1`.
 //!     .resolve::<dyn Trait2>("trait2")
 //!     .expect("Should exist");
 //! println!("Deep description: {}", trait2.deep_describe());
@@ -218,11 +216,16 @@
 //!
 //! *  Formatting a container with `{:?}` will also list the dependencies (in A: Vec&lt;B&gt; style)
 //! *  `Container` will get an [`analyze`] fn, which will return an error if any misconfiguration is
-//! detected. See the docs for [`analyze`] for more details.
+//!    detected. See the docs for [`analyze`] for more details.
 //! *  `Container` will get a [`dot_graph`] fn, which will return a string that can be passed to
-//! [graphviz]'s dot command to generate a graph. The image below was generated with the sample
-//! project that's in this crate's repository (output saved to `deps.dot` then ran
-//! `dot -Tsvg deps.dot -o deps.svg `):
+//!    [graphviz]'s dot command to generate a graph. The image below was generated with the sample
+//!    project that's in this crate's repository (output saved to `deps.dot` then ran
+//!    `dot -Tsvg deps.dot -o deps.svg `):
+//!
+//! The dependencies come from [`Provide::dependencies`], which the derive macros and
+//! [`provide_closure`] fill in. Enabling the feature doesn't change any trait, so a crate that
+//! implements `Provide` by hand or registers plain closures keeps compiling when another crate
+//! turns it on; the analysis just can't see the dependencies of those providers.
 //!
 //! <div>
 //! <svg width="168pt" height="188pt"
@@ -266,6 +269,8 @@
 //!
 //! [`analyze`]: struct.Container.html#method.analyze
 //! [`dot_graph`]: struct.Container.html#method.dot_graph
+//! [`Provide::dependencies`]: trait.Provide.html#method.dependencies
+//! [`provide_closure`]: macro.provide_closure.html
 //! [graphviz]: https://www.graphviz.org/
 //!
 //! # Features
@@ -290,8 +295,21 @@
 //!
 //! - default: `derive` - Procedural macros are re-exported.
 //! - debug: `Debug` impl
+//! - tracing: Every resolution is made inside a `resolve` span at the debug level, see
+//!   [Tracing](#tracing).
 //! - None - Procedural macros are not re-exported.
 //!
+//! # Tracing
+//!
+//! With the `tracing` feature, each key a container resolves gets a [`tracing`] span named
+//! `resolve`, with the `key`, the `type_name` it was requested as and its registration `kind`.
+//! `cached` records whether the instance came from the container's cache rather than its
+//! provider, and `error` records why the resolution failed, if it did. Dependencies are resolved
+//! inside the span of the key that depends on them, so a subscriber sees the whole construction
+//! tree.
+//!
+//! [`tracing`]: https://docs.rs/tracing
+//!
 //! # Help
 //!
 //! ## External traits
@@ -353,9 +371,23 @@
 //! [`std::any::Any`]: https://doc.rust-lang.org/std/any/trait.Any.html
 //! [tracking issue]: https://github.com/rust-lang/rust/issues/41875
 
+use arc_swap::{ArcSwapOption, Guard};
 use rustc_hash::FxHashMap as HashMap;
-use std::any::Any;
-use std::sync::{Arc, Mutex};
+use rustc_hash::FxHashSet as HashSet;
+use std::any::{Any, TypeId};
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe, Location};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError, Weak};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 
 #[cfg(any(feature = "derive", feature = "debug"))]
 pub use coi_derive::*;
@@ -365,26 +397,174 @@ use petgraph::{
     graph::{DiGraph, NodeIndex},
 };
 #[cfg(feature = "debug")]
-use std::fmt::{self, Debug};
+use std::fmt::Debug;
 
 /// Errors produced by this crate
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     /// This key was not found in the container. Either the requested resource was never registered
     /// with this container, or there is a typo in the register or resolve calls.
-    #[error("Key not found: {0}")]
-    KeyNotFound(String),
+    #[error("Key not found: {key} (requested as `{type_name}`, resolving {})", .path.join(" -> "))]
+    KeyNotFound {
+        /// The key that was not found.
+        key: String,
+        /// The name of the type the key was requested as.
+        type_name: &'static str,
+        /// The keys that were being resolved, outermost first and ending with `key`.
+        path: Vec<String>,
+    },
     /// The requested key was found in the container, but its type did not match the requested type.
-    #[error("Type mismatch for key: {0}")]
-    TypeMismatch(String),
+    #[error("Type mismatch for key: {key} (requested as `{type_name}`, resolving {})", .path.join(" -> "))]
+    TypeMismatch {
+        /// The key whose registered type did not match.
+        key: String,
+        /// The name of the type the key was requested as.
+        type_name: &'static str,
+        /// The keys that were being resolved, outermost first and ending with `key`.
+        path: Vec<String>,
+    },
+    /// The requested key was registered with an [`AsyncProvide`] provider, and can only be
+    /// resolved through [`Container::resolve_async`].
+    ///
+    /// [`AsyncProvide`]: trait.AsyncProvide.html
+    /// [`Container::resolve_async`]: struct.Container.html#method.resolve_async
+    #[error("Key requires async resolution: {0}")]
+    RequiresAsync(String),
+    /// The requested key was registered with a [`ProvideWith`] provider, and can only be
+    /// resolved through [`Container::resolve_with`].
+    ///
+    /// [`ProvideWith`]: trait.ProvideWith.html
+    /// [`Container::resolve_with`]: struct.Container.html#method.resolve_with
+    #[error("Key requires arguments: {0}")]
+    RequiresArguments(String),
+    /// A key depends on itself, either directly or through its dependencies. Contains the keys
+    /// that were being resolved, outermost first and ending with the repeated key.
+    ///
+    /// Cycles through a clone of the container handed to a provider, or through a [`Lazy`] or
+    /// [`Factory`] created from it, are detected as long as that provider is still running.
+    /// Scoped and singleton keys are also reported when the cycle goes through another thread,
+    /// rather than blocking forever. The path then only holds the keys resolved by the failing
+    /// call.
+    ///
+    /// [`Lazy`]: struct.Lazy.html
+    /// [`Factory`]: struct.Factory.html
+    #[error("Cycle detected: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    /// A [`Lazy`] or [`Factory`] tried to resolve its key after the container it came from was
+    /// dropped.
+    ///
+    /// [`Lazy`]: struct.Lazy.html
+    /// [`Factory`]: struct.Factory.html
+    #[error("Container dropped before resolving key: {0}")]
+    ContainerDropped(String),
+    /// An instance has an async disposer, so [`Container::dispose`] left it in the container.
+    /// It's disposed of by [`Container::dispose_async`] instead.
+    ///
+    /// [`Container::dispose`]: struct.Container.html#method.dispose
+    /// [`Container::dispose_async`]: struct.Container.html#method.dispose_async
+    #[error("Key requires async disposal: {0}")]
+    RequiresAsyncDispose(String),
+    /// One or more instances failed to be disposed by [`Container::dispose`]. Contains the key
+    /// of each instance along with the error its disposer returned.
+    ///
+    /// [`Container::dispose`]: struct.Container.html#method.dispose
+    #[error("Failed to dispose: {}", display_key_errors(.0))]
+    Dispose(Vec<(String, Error)>),
+    /// One or more singletons failed to be constructed by [`ContainerBuilder::build_eager`].
+    /// Contains the key of each singleton along with the error resolving it returned.
+    ///
+    /// [`ContainerBuilder::build_eager`]: struct.ContainerBuilder.html#method.build_eager
+    #[error("Failed to construct singletons: {}", display_key_errors(.0))]
+    Eager(Vec<(String, Error)>),
+    /// One or more modules depend on modules that weren't added to the [`ContainerBuilder`].
+    /// Contains the name of each module along with the module it's missing.
+    ///
+    /// [`ContainerBuilder`]: struct.ContainerBuilder.html
+    #[error("Missing modules: {}", display_missing_modules(.0))]
+    MissingModules(Vec<(String, String)>),
+    /// One or more modules registered keys qualified with the name of another module, which only
+    /// that module can register. Contains the name of each module along with the key it
+    /// registered.
+    #[error("Keys registered outside of their module: {}", display_module_keys(.0))]
+    ForeignModuleKeys(Vec<(String, String)>),
+    /// One or more modules export keys they never registered. Contains the name of each module
+    /// along with the export it's missing.
+    #[error("Exported keys were never registered: {}", display_module_keys(.0))]
+    UnknownExports(Vec<(String, String)>),
+    /// One or more keys were registered more than once with [`DuplicatePolicy::Error`], found
+    /// by [`ContainerBuilder::try_build`].
+    ///
+    /// [`DuplicatePolicy::Error`]: enum.DuplicatePolicy.html#variant.Error
+    /// [`ContainerBuilder::try_build`]: struct.ContainerBuilder.html#method.try_build
+    #[error("Duplicate keys: {}", display_duplicates(.0))]
+    DuplicateKeys(Vec<DuplicateKey>),
+    /// A scoped key was resolved while constructing a singleton, which would keep the scoped
+    /// instance alive for as long as the singleton. Only checked by containers built with
+    /// [`ContainerBuilder::check_captive_dependencies`].
+    ///
+    /// [`ContainerBuilder::check_captive_dependencies`]: struct.ContainerBuilder.html#method.check_captive_dependencies
+    #[error("Captive dependency: singleton {consumer} depends on scoped {dependency} (resolving {})", .path.join(" -> "))]
+    CaptiveDependency {
+        /// The singleton being constructed.
+        consumer: String,
+        /// The scoped key it depends on.
+        dependency: String,
+        /// The keys that were being resolved, outermost first and ending with `dependency`.
+        path: Vec<String>,
+    },
+    /// The provider for a key panicked. The panic was caught, so the container can still be
+    /// used, and resolving the key again runs its provider again.
+    #[error("Provider panicked for key: {key} ({message})")]
+    ProviderPanicked {
+        /// The key whose provider panicked.
+        key: String,
+        /// The message the provider panicked with.
+        message: String,
+    },
     /// Wrapper around errors produced by `Provider`s.
     #[error("Inner error: {0}")]
     Inner(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
+fn display_missing_modules(missing: &[(String, String)]) -> String {
+    missing
+        .iter()
+        .map(|(module, dependency)| format!("{} (required by {})", dependency, module))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn display_module_keys(keys: &[(String, String)]) -> String {
+    keys.iter()
+        .map(|(module, key)| format!("{} (in {})", key, module))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn display_duplicates(duplicates: &[DuplicateKey]) -> String {
+    duplicates
+        .iter()
+        .map(|duplicate| duplicate.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn display_key_errors(errors: &[(String, Error)]) -> String {
+    errors
+        .iter()
+        .map(|(key, error)| format!("{} ({})", key, error))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// Type alias to `Result<T, coi::Error>`.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Type alias to a boxed future, as produced by [`AsyncProvide::provide`].
+///
+/// [`AsyncProvide::provide`]: trait.AsyncProvide.html#tymethod.provide
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
 /// A marker trait for injectable traits and structs.
 pub trait Inject: Send + Sync + 'static {}
 
@@ -521,370 +701,4072 @@ impl<T> Registration<T> {
     }
 }
 
+/// A registration of a container, as listed by [`Container::registrations`].
+///
+/// [`Container::registrations`]: struct.Container.html#method.registrations
 #[derive(Clone, Debug)]
-struct InnerContainer {
-    provider_map: HashMap<&'static str, Registration<Arc<dyn Any + Send + Sync>>>,
-    resolved_map: HashMap<&'static str, Arc<dyn Any + Send + Sync>>,
-    parent: Option<Container>,
-    #[cfg(feature = "debug")]
-    dependency_map: HashMap<&'static str, &'static [&'static str]>,
+pub struct RegistrationInfo {
+    /// The key the registration was made with, displayed the way errors display it.
+    pub key: String,
+    /// When the container calls the registration's provider.
+    pub kind: RegistrationKind,
+    /// The name of the type the key resolves to, as returned by `std::any::type_name`.
+    pub type_name: &'static str,
+    /// The keys the provider depends on, or `None` if it doesn't say. See
+    /// [`Provide::dependencies`].
+    ///
+    /// [`Provide::dependencies`]: trait.Provide.html#method.dependencies
+    pub dependencies: Option<&'static [&'static str]>,
+    /// The keys the provider can do without. See [`Provide::optional_dependencies`].
+    ///
+    /// [`Provide::optional_dependencies`]: trait.Provide.html#method.optional_dependencies
+    pub optional_dependencies: &'static [&'static str],
 }
 
-impl InnerContainer {
-    fn check_resolved<T>(&self, key: &'static str) -> Option<Result<Arc<T>>>
+impl Registration<Arc<dyn Any + Send + Sync>> {
+    fn any_provider<T>(&self) -> Option<AnyProvider<T>>
     where
         T: Inject + ?Sized,
     {
-        self.resolved_map.get(key).map(|v| {
-            v.downcast_ref::<Arc<T>>()
-                .map(Arc::clone)
-                .ok_or_else(|| Error::TypeMismatch(key.to_owned()))
-        })
+        if let Some(provider) = self
+            .provider
+            .downcast_ref::<Arc<dyn Provide<Output = T> + Send + Sync>>()
+        {
+            return Some(AnyProvider::Sync(Arc::clone(provider)));
+        }
+
+        self.provider
+            .downcast_ref::<Arc<dyn AsyncProvide<Output = T> + Send + Sync>>()
+            .map(|provider| AnyProvider::Async(Arc::clone(provider)))
     }
 }
 
-/// A struct that manages all injected types.
-#[derive(Clone, Debug)]
-pub struct Container(Arc<Mutex<InnerContainer>>);
-
-/// Possible errors generated when running [`Container::analyze`].
-///
-/// [`Container::analyze`]: struct.Container.html#method.analyze
-#[cfg(feature = "debug")]
-#[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
-#[derive(Debug, thiserror::Error)]
-pub enum AnalysisError {
-    // FIXME(pfaria), it would be better if we could trace the
-    // entire cycle and store a Vec<String> here. Might require
-    // manually calling petgraph::visit::depth_first_search
-    /// There is a cyclic dependency within the container
-    #[error("Cycle detected at node `{0}`")]
-    Cycle(&'static str),
-    /// There is a missing dependency. Param 0 depends on Param 1, and Param 1 is missing.
-    #[error("Node `{0}` depends on `{1}`, the latter of which is not registered")]
-    Missing(&'static str, &'static str),
+/// The identifier a registration is stored under: either the string it was registered with, the
+/// type it provides, or its position among the registrations of a collection. Strings registered
+/// by a module are qualified with the module's name.
+#[derive(Clone, Copy)]
+enum Key {
+    Name(&'static str),
+    Module(&'static str, &'static str),
+    Type(TypeId, &'static str),
+    Item(&'static str, usize),
 }
 
-#[cfg(feature = "debug")]
-#[derive(Clone, Default)]
-struct AnalysisNode {
-    registration: Option<RegistrationKind>,
-    id: &'static str,
-}
+impl Key {
+    fn of<T>() -> Self
+    where
+        T: Inject + ?Sized,
+    {
+        Key::Type(TypeId::of::<T>(), std::any::type_name::<T>())
+    }
 
-#[cfg(feature = "debug")]
-impl fmt::Display for AnalysisNode {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.registration {
-            Some(reg) => match reg {
-                RegistrationKind::Transient => write!(f, "Transient - {}", self.id),
-                RegistrationKind::Singleton => write!(f, "Singleton - {}", self.id),
-                RegistrationKind::Scoped => write!(f, "Scoped - {}", self.id),
-            },
-            None => write!(f, "MISSING - {}", self.id),
+    /// The key for the string `name`. Names like `auth::repo` refer to the `repo` key registered
+    /// by the `auth` module.
+    fn named(name: &'static str) -> Self {
+        match name.rsplit_once("::") {
+            Some((module, name)) => Key::Module(module, name),
+            None => Key::Name(name),
         }
     }
-}
 
-impl Container {
-    fn new(container: InnerContainer) -> Self {
-        Self(Arc::new(Mutex::new(container)))
+    /// The name of the registration, without its module, or of the collection for collection
+    /// items.
+    fn name(&self) -> &'static str {
+        match self {
+            Key::Name(name) | Key::Module(_, name) | Key::Type(_, name) | Key::Item(name, _) => {
+                name
+            }
+        }
     }
+}
 
-    /// Resolve an `Arc<T>` whose provider was previously registered with `key`.
-    pub fn resolve<T>(&self, key: &'static str) -> Result<Arc<T>>
-    where
-        T: Inject + ?Sized,
-    {
-        let (kind, provider) = {
-            let container = self.0.lock().unwrap();
-            // If we already have a resolved version, return it.
-            if let Some(resolved) = container.check_resolved::<T>(key) {
-                return resolved;
-            }
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Key::Name(a), Key::Name(b)) => a == b,
+            (Key::Module(m, a), Key::Module(n, b)) => m == n && a == b,
+            (Key::Type(a, _), Key::Type(b, _)) => a == b,
+            (Key::Item(a, i), Key::Item(b, j)) => a == b && i == j,
+            _ => false,
+        }
+    }
+}
 
-            // Try to find the provider
-            let registration = match container.provider_map.get(key) {
-                Some(provider) => provider,
-                None => {
-                    // If the key is not found, then we might be a child container. If we have a
-                    // parent, then search it for a possibly valid provider.
-                    return match &container.parent {
-                        Some(parent) => {
-                            let parent = parent.clone();
-                            // Release the lock so we don't deadlock, this container isn't
-                            // needed anymore
-                            parent.resolve::<T>(key)
-                        }
-                        None => Err(Error::KeyNotFound(key.to_owned())),
-                    };
-                }
-            };
+impl Eq for Key {}
 
-            (
-                registration.kind,
-                registration
-                    .provider
-                    .downcast_ref::<Arc<dyn Provide<Output = T> + Send + Sync + 'static>>()
-                    .map(Arc::clone)
-                    .ok_or_else(|| Error::TypeMismatch(key.to_owned()))?,
-            )
-        };
-        let provided = provider.provide(self);
-
-        match kind {
-            RegistrationKind::Transient => provided,
-            RegistrationKind::Scoped | RegistrationKind::Singleton => {
-                let mut container = self.0.lock().unwrap();
-                // Since there's a possibility for a deadlock right now, we want to make sure
-                // no one else already inserted into the resolved map (hence the call to entry).
-                Ok(container
-                    .resolved_map
-                    .entry(key)
-                    .or_insert(Arc::new(provided?))
-                    .downcast_ref::<Arc<T>>()
-                    .map(Arc::clone)
-                    .unwrap())
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Key::Name(name) => name.hash(state),
+            Key::Module(module, name) => {
+                module.hash(state);
+                name.hash(state);
+            }
+            Key::Type(id, _) => id.hash(state),
+            Key::Item(name, index) => {
+                name.hash(state);
+                index.hash(state);
             }
         }
     }
+}
 
-    /// Produce a child container that only contains providers for scoped registrations
-    /// Any calls to resolve from the returned container can still use the `self` container
-    /// to resolve any other kinds of registrations.
-    pub fn scoped(&self) -> Container {
-        let container: &InnerContainer = &self.0.lock().unwrap();
-        Container::new(InnerContainer {
-            provider_map: container
-                .provider_map
-                .iter()
-                .filter_map(|(k, v)| match v.kind {
-                    kind @ RegistrationKind::Scoped | kind @ RegistrationKind::Transient => Some((
-                        *k,
-                        Registration {
-                            kind,
-                            provider: Arc::clone(&v.provider),
-                        },
-                    )),
-                    _ => None,
-                })
-                .collect(),
-            resolved_map: HashMap::default(),
-            // FIXME(pfaria) no clone here
-            #[cfg(feature = "debug")]
-            dependency_map: container.dependency_map.clone(),
-            parent: Some(self.clone()),
-        })
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Module(module, name) => write!(f, "{}::{}", module, name),
+            Key::Item(name, index) => write!(f, "{}[{}]", name, index),
+            _ => f.write_str(self.name()),
+        }
     }
+}
 
-    #[cfg(feature = "debug")]
-    fn dependency_graph(&self) -> DiGraph<AnalysisNode, AnalysisNode> {
-        let container = self.0.lock().unwrap();
-        let mut graph = DiGraph::<AnalysisNode, AnalysisNode>::new();
-        let mut key_to_node = container
-            .dependency_map
-            .keys()
-            .map(|k| -> (&'static str, NodeIndex) {
-                let kind = container.provider_map[k].kind;
-                let n = graph.add_node(AnalysisNode {
-                    registration: Some(kind),
-                    id: k,
-                });
-                (k, n)
-            })
-            .collect::<HashMap<&str, _>>();
-        for (k, deps) in &container.dependency_map {
-            let kn = key_to_node[k as &str];
-            let edges = deps
-                .iter()
-                .map(|dep| {
-                    let vn = match key_to_node.get(dep) {
-                        Some(vn) => *vn,
-                        None => {
-                            let vn = graph.add_node(AnalysisNode {
-                                registration: None,
-                                id: dep,
-                            });
-                            key_to_node.insert(dep, vn);
-                            key_to_node[dep]
-                        }
-                    };
-                    (kn, vn)
-                })
-                .collect::<Vec<_>>();
-            graph.extend_with_edges(&edges[..]);
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Name(name) => write!(f, "{:?}", name),
+            Key::Module(module, name) => write!(f, "\"{}::{}\"", module, name),
+            Key::Type(_, name) => write!(f, "<{}>", name),
+            Key::Item(name, index) => write!(f, "{:?}[{}]", name, index),
         }
-
-        graph
     }
+}
 
-    // FIXME(pfaria): Add analysis on singleton registrations that depend on
-    // non-singleton registration.
-    /// Run an analysis on a container and return any issues detected.
-    /// Current analysis performed:
-    /// - Missing dependencies
-    /// - Cyclic dependencies
-    #[cfg(feature = "debug")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
-    pub fn analyze(&self) -> std::result::Result<(), Vec<AnalysisError>> {
-        use petgraph::Direction;
-        let graph = self.dependency_graph();
-        let mut errors = graph
-            .node_indices()
-            .filter(|i| graph[*i].registration.is_none())
-            .flat_map(|i| {
-                let to = &graph[i].id;
-                graph
-                    .neighbors_directed(i, Direction::Incoming)
-                    .map(|from| AnalysisError::Missing(graph[from].id, to))
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
+enum AnyProvider<T: ?Sized> {
+    Sync(Arc<dyn Provide<Output = T> + Send + Sync>),
+    Async(Arc<dyn AsyncProvide<Output = T> + Send + Sync>),
+}
 
-        // Do any cycles exist?
-        if let Err(cycle) = toposort(&graph, None) {
-            errors.push(AnalysisError::Cycle(graph[cycle.node_id()].id));
-        }
+/// An instance cached by a container, always an `Arc<T>` for the type `T` it was resolved as.
+type Resolved = Box<dyn Any + Send + Sync>;
 
-        if !errors.is_empty() {
-            Err(errors)
-        } else {
-            Ok(())
-        }
-    }
+/// Where a container caches the instance of one scoped or singleton registration.
+#[derive(Debug, Default)]
+struct Slot {
+    // Only initialized once something is cached, as dropping an `ArcSwapOption` isn't free and
+    // most scopes never resolve most of their scoped registrations.
+    resolved: OnceLock<ArcSwapOption<Resolved>>,
+    // The thread whose sync provider is constructing the instance, so that it only runs once.
+    init: Mutex<Option<ThreadId>>,
+    // Notified once that thread is done, whether it succeeded or not.
+    initialized: Condvar,
+}
 
-    /// Produces a dot format output that can be processed by the [graphviz] [`dot` (pdf)]
-    /// program to generate a graphical representation of the dependency graph.
-    ///
-    /// [graphviz]: http://graphviz.org/
-    /// [`dot` (pdf)]: https://graphviz.gitlab.io/_pages/pdf/dotguide.pdf
-    #[cfg(feature = "debug")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
-    pub fn dot_graph(&self) -> String {
-        use petgraph::dot::{Config, Dot};
-        let graph = self.dependency_graph();
-        format!("{}", Dot::with_config(&graph, &[Config::EdgeNoLabel]))
+/// The outcome of `Slot::claim`.
+enum Claim<'a> {
+    // Another resolution cached an instance in the meantime.
+    Cached(Arc<Resolved>),
+    // The current thread gets to run the provider, until the guard is dropped.
+    Claimed(ClaimGuard<'a>),
+    // The construction to wait on depends on the current thread's own, so it'd never finish.
+    Cycle,
+}
+
+/// Releases a slot claimed with `Slot::claim` when dropped, waking the threads waiting on it.
+struct ClaimGuard<'a>(&'a Slot);
+
+impl Drop for ClaimGuard<'_> {
+    fn drop(&mut self) {
+        // Nobody waits on this thread for the slot anymore, which has to be known before the
+        // thread goes on to wait on anything else itself.
+        let slot = self.0.address();
+        lock(&WAITS).retain(|wait| !matches!(wait, Wait::Thread { slot: s, .. } if *s == slot));
+        *lock(&self.0.init) = None;
+        self.0.initialized.notify_all();
     }
 }
 
-/// A builder used to construct a `Container`.
-#[derive(Clone, Default)]
-pub struct ContainerBuilder {
-    provider_map: HashMap<&'static str, Registration<Arc<dyn Any + Send + Sync>>>,
-    #[cfg(feature = "debug")]
-    dependency_map: HashMap<&'static str, &'static [&'static str]>,
+/// A resolution that can't go on until another one is done constructing an instance.
+enum Wait {
+    // A thread blocked in `Slot::claim` until `owner` is done constructing the instance of
+    // `slot`.
+    Thread {
+        waiter: ThreadId,
+        slot: usize,
+        owner: ThreadId,
+    },
+    // A `Container::resolve_async` call, identified by the address of the frame it entered,
+    // awaiting the construction running in the frame at `owner`. `path` holds the addresses of
+    // the frames it was made from, none of which can finish before it does. Tasks can await
+    // several constructions at once, so they're told apart by frame instead of by thread.
+    Task {
+        frame: usize,
+        path: Vec<usize>,
+        owner: usize,
+    },
 }
 
-impl ContainerBuilder {
-    /// Constructor for `ContainerBuilder`.
-    pub fn new() -> Self {
-        Self {
-            provider_map: HashMap::default(),
-            #[cfg(feature = "debug")]
-            dependency_map: HashMap::default(),
+// Every resolution currently waiting on another one. Each thread waits on at most one slot, so
+// following the owners from one wait to the next finds out whether waiting would deadlock.
+// Sync providers can't await, so the threads and tasks waiting never depend on each other.
+static WAITS: Mutex<Vec<Wait>> = Mutex::new(Vec::new());
+
+impl Slot {
+    fn load(&self) -> Guard<Option<Arc<Resolved>>> {
+        match self.resolved.get() {
+            Some(resolved) => resolved.load(),
+            None => Guard::from_inner(None),
         }
     }
 
-    /// Register a `Provider` for `T` with identifier `key`.
-    #[inline]
-    pub fn register<P, T>(self, key: &'static str, provider: P) -> Self
-    where
-        T: Inject + ?Sized,
-        P: Provide<Output = T> + Send + Sync + 'static,
-    {
-        self.register_as(
-            key,
-            Registration::new(RegistrationKind::Transient, provider),
-        )
+    fn store(&self, resolved: Arc<Resolved>) {
+        self.resolved
+            .get_or_init(ArcSwapOption::empty)
+            .store(Some(resolved));
     }
 
-    fn get_arc<P, T>(provider: P) -> Arc<dyn Provide<Output = T> + Send + Sync>
-    where
-        T: Inject + ?Sized,
-        P: Provide<Output = T> + Send + Sync + 'static,
-    {
-        Arc::new(provider)
+    fn take(&self) -> Option<Arc<Resolved>> {
+        self.resolved.get()?.swap(None)
     }
 
-    /// Register a `Provider` for `T` with identifier `key`, while also specifying the resolution
-    /// behavior.
-    pub fn register_as<P, T>(mut self, key: &'static str, registration: Registration<P>) -> Self
-    where
-        T: Inject + ?Sized,
-        P: Provide<Output = T> + Send + Sync + 'static,
-    {
-        #[cfg(feature = "debug")]
-        let deps = registration.provider.dependencies();
-        self.provider_map.insert(
-            key,
-            Registration {
-                kind: registration.kind,
-                provider: Arc::new(Self::get_arc(registration.provider))
-                    as Arc<dyn Any + Send + Sync>,
-            },
-        );
-        #[cfg(feature = "debug")]
-        self.dependency_map.insert(key, deps);
-        self
+    fn address(&self) -> usize {
+        self as *const Slot as usize
     }
 
-    /// Consume this builder to produce a `Container`.
-    pub fn build(self) -> Container {
-        Container::new(InnerContainer {
-            provider_map: self.provider_map,
-            resolved_map: HashMap::default(),
-            parent: None,
-            #[cfg(feature = "debug")]
-            dependency_map: self.dependency_map,
-        })
+    /// Claim the construction of this slot's instance for the current thread, first waiting
+    /// for any other thread constructing it. A thread that's already constructing the instance,
+    /// or that the other thread is waiting on, gets `Claim::Cycle` instead of blocking forever.
+    fn claim(&self) -> Claim<'_> {
+        let current = thread::current().id();
+        let mut owner = lock(&self.init);
+        loop {
+            if let Some(resolved) = &*self.load() {
+                return Claim::Cached(Arc::clone(resolved));
+            }
+            match *owner {
+                None => {
+                    *owner = Some(current);
+                    return Claim::Claimed(ClaimGuard(self));
+                }
+                Some(other) if other == current => return Claim::Cycle,
+                Some(other) => {
+                    {
+                        let mut waits = lock(&WAITS);
+                        // Follow what `other` is waiting on, and what that is waiting on, etc.
+                        let mut next = Some(other);
+                        while let Some(thread) = next {
+                            if thread == current {
+                                return Claim::Cycle;
+                            }
+                            next = waits.iter().find_map(|wait| match wait {
+                                Wait::Thread { waiter, owner, .. } if *waiter == thread => {
+                                    Some(*owner)
+                                }
+                                _ => None,
+                            });
+                        }
+                        waits.push(Wait::Thread {
+                            waiter: current,
+                            slot: self.address(),
+                            owner: other,
+                        });
+                    }
+                    owner = self
+                        .initialized
+                        .wait(owner)
+                        .unwrap_or_else(PoisonError::into_inner);
+                    lock(&WAITS).retain(
+                        |wait| !matches!(wait, Wait::Thread { waiter, .. } if *waiter == current),
+                    );
+                }
+            }
+        }
     }
 }
 
-/// A trait to manage the construction of an injectable trait or struct.
-pub trait Provide {
-    /// The type that this provider will produce when resolved from a [`Container`].
-    ///
-    /// [`Container`]: struct.Container.html
-    type Output: Inject + ?Sized;
-
-    /// Only intended to be used internally
-    fn provide(&self, container: &Container) -> Result<Arc<Self::Output>>;
+/// The registrations of a container. They never change once it's built, so its scopes share
+/// them instead of each getting a copy.
+#[derive(Debug)]
+struct Registrations {
+    entries: HashMap<Key, RegistrationEntry>,
+    // The number of providers registered under each collection key. Each one has an entry under
+    // a `Key::Item`.
+    collection_map: HashMap<&'static str, usize>,
+    // The registrations these were added to by `Container::scoped_with` or
+    // `Container::with_overrides`. Entries here take precedence over the ones in `base`.
+    base: Option<Arc<Registrations>>,
+    // Whether resolving fails with `Error::CaptiveDependency`, inherited from `base`.
+    check_captive: bool,
+    // Notified of every resolution, including the ones added to `base`.
+    observers: Observers,
+    // The number of slots a scope needs for the scoped registrations here and in `base`.
+    scoped_slots: usize,
+    // The number of slots the container that owns these registrations needs, as it also caches
+    // the singletons registered here.
+    slots: usize,
+    dependency_map: HashMap<Key, Dependencies>,
+    #[cfg(feature = "debug")]
+    decorator_map: HashMap<Key, usize>,
+}
 
-    /// Return list of dependencies
+#[derive(Debug)]
+struct RegistrationEntry {
+    registration: Registration<Arc<dyn Any + Send + Sync>>,
+    // The name of the type the key resolves to.
+    type_name: &'static str,
+    disposer: Option<AnyDisposer>,
+    // The module the key was registered by, if any.
+    module: Option<ModuleEntry>,
+    // The slot instances are cached in, unless the registration is transient.
+    slot: Option<usize>,
+    // Whether the key was registered with `ContainerBuilder::register_with`, and so can only be
+    // resolved with arguments.
+    with_args: bool,
+    // Whether the key was registered with `ContainerBuilder::register_instance`.
     #[cfg(feature = "debug")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
-    fn dependencies(&self) -> &'static [&'static str];
+    instance: bool,
 }
 
-#[cfg(not(feature = "debug"))]
-#[cfg_attr(docsrs, doc(cfg(not(feature = "debug"))))]
-impl<T, F> Provide for F
-where
-    F: Fn(&Container) -> Result<Arc<T>>,
-    T: Inject + ?Sized,
-{
-    type Output = T;
+impl Registrations {
+    /// The entry for `key`, along with the registrations it was found in.
+    fn get(&self, key: Key) -> Option<(&Registrations, &RegistrationEntry)> {
+        self.layers()
+            .find_map(|layer| layer.entries.get(&key).map(|entry| (layer, entry)))
+    }
 
-    fn provide(&self, container: &Container) -> Result<Arc<Self::Output>> {
-        self(container)
+    /// The number of providers registered under the collection `key`, if any.
+    fn collection(&self, key: &str) -> Option<usize> {
+        self.layers()
+            .filter_map(|layer| layer.collection_map.get(key).copied())
+            .max()
     }
-}
 
-#[cfg(not(feature = "debug"))]
-#[cfg_attr(docsrs, doc(cfg(not(feature = "debug"))))]
-impl<T> Provide for dyn Fn(&Container) -> Result<Arc<T>>
-where
-    T: Inject + ?Sized,
-{
-    type Output = T;
+    /// These registrations, followed by the ones they were added to.
+    fn layers(&self) -> impl Iterator<Item = &Registrations> {
+        std::iter::successors(Some(self), |layer| layer.base.as_deref())
+    }
+
+    /// Every key, along with the entry it currently refers to and the registrations that entry
+    /// was found in.
+    fn entries(&self) -> Vec<(Key, &Registrations, &RegistrationEntry)> {
+        let keys = self
+            .layers()
+            .flat_map(|layer| layer.entries.keys().copied())
+            .collect::<HashSet<_>>();
+        keys.into_iter()
+            .filter_map(|key| {
+                let (layer, entry) = self.get(key)?;
+                Some((key, layer, entry))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct InnerContainer {
+    registrations: Arc<Registrations>,
+    // Whether this container added `registrations`, rather than sharing its parent's the way a
+    // scope does. Singletons are only cached by the container that owns their registration.
+    owner: bool,
+    // Where the instances of scoped registrations are cached, followed by the ones of singletons
+    // if this container owns them. Only the contents of a slot ever change after the container
+    // is built, so looking up a resolved instance doesn't need to lock anything.
+    slots: Box<[Slot]>,
+    // The keys of cached instances that have a disposer, in the order they were resolved.
+    created: Mutex<Vec<Key>>,
+    // Keys whose provider is currently being awaited by `Container::resolve_async`.
+    in_flight: Mutex<HashMap<Key, InFlight>>,
+    parent: Option<Container>,
+}
+
+/// The module a key was registered by.
+#[derive(Clone, Copy, Debug)]
+struct ModuleEntry {
+    module: &'static str,
+    // Whether the key can be resolved from outside of the module.
+    exported: bool,
+}
+
+impl InnerContainer {
+    /// A container with an empty slot for every instance it caches. Creating one only allocates
+    /// those slots, however many registrations there are.
+    fn new(registrations: Arc<Registrations>, owner: bool, parent: Option<Container>) -> Self {
+        let slots = if owner {
+            registrations.slots
+        } else {
+            registrations.scoped_slots
+        };
+        Self {
+            slots: (0..slots).map(|_| Slot::default()).collect(),
+            registrations,
+            owner,
+            created: Mutex::default(),
+            in_flight: Mutex::default(),
+            parent,
+        }
+    }
+
+    /// Cache each of `instances`, registered with `ContainerBuilder::register_instance`, so that
+    /// their providers are never invoked.
+    fn with_instances(self, instances: HashMap<Key, Arc<Resolved>>) -> Self {
+        for (key, instance) in instances {
+            if let Some(slot) = self.registrations.get(key).and_then(|(_, e)| self.slot(e)) {
+                slot.store(instance);
+            }
+        }
+        self
+    }
+
+    /// Whether `entry`, found in `registrations`, is resolved by this container rather than by
+    /// its parent. Singletons are resolved by the container that owns their registration, so
+    /// that all of its scopes share them.
+    fn resolves(&self, registrations: &Registrations, entry: &RegistrationEntry) -> bool {
+        match entry.registration.kind {
+            RegistrationKind::Singleton => {
+                self.owner && std::ptr::eq(registrations, &*self.registrations)
+            }
+            RegistrationKind::Scoped | RegistrationKind::Transient => true,
+        }
+    }
+
+    /// This container followed by its parents, closest first.
+    fn ancestors(&self) -> impl Iterator<Item = &InnerContainer> {
+        std::iter::successors(Some(self), |container| {
+            container.parent.as_ref().map(|parent| &*parent.inner)
+        })
+    }
+
+    fn address(&self) -> usize {
+        self as *const InnerContainer as usize
+    }
+
+    /// The slot instances of `entry` are cached in, or `None` if it's transient.
+    fn slot(&self, entry: &RegistrationEntry) -> Option<&Slot> {
+        entry.slot.map(|slot| &self.slots[slot])
+    }
+
+    /// The instance cached for `entry`, if it has been resolved. Loading it doesn't touch its
+    /// reference count, so concurrent lookups of the same key don't contend with each other.
+    fn resolved(&self, entry: &RegistrationEntry) -> Guard<Option<Arc<Resolved>>> {
+        match self.slot(entry) {
+            Some(slot) => slot.load(),
+            None => Guard::from_inner(None),
+        }
+    }
+
+    /// Cache `resolved` for `key` and return it. Only the resolution that constructed the
+    /// instance calls this, so nothing else can have been cached for `key` in the meantime.
+    fn insert_resolved(
+        &self,
+        key: Key,
+        entry: &RegistrationEntry,
+        resolved: Resolved,
+    ) -> Arc<Resolved> {
+        let resolved = Arc::new(resolved);
+        if let Some(slot) = self.slot(entry) {
+            slot.store(Arc::clone(&resolved));
+        }
+        if entry.disposer.is_some() {
+            lock(&self.created).push(key);
+        }
+        resolved
+    }
+
+    /// Remove every instance that has a disposer, most recently created first. Unless
+    /// `include_async`, instances with an async disposer are left in the container to be
+    /// disposed of later, and their keys are returned alongside.
+    fn take_disposable(&self, include_async: bool) -> (Vec<Disposable>, Vec<Key>) {
+        let mut created = lock(&self.created);
+        let (mut disposable, mut kept) = (vec![], vec![]);
+        for key in std::mem::take(&mut *created).into_iter().rev() {
+            let entry = match self.registrations.get(key) {
+                Some((_, entry)) => entry,
+                None => continue,
+            };
+            let disposer = match &entry.disposer {
+                Some(disposer) => disposer.clone(),
+                None => continue,
+            };
+            if !include_async && matches!(disposer, AnyDisposer::Async(_)) {
+                kept.push(key);
+                continue;
+            }
+            if let Some(resolved) = self.slot(entry).and_then(Slot::take) {
+                disposable.push((key, resolved, disposer));
+            }
+        }
+        // Keep the creation order, so a later `dispose_async` still goes from newest to oldest.
+        created.extend(kept.iter().rev().copied());
+        (disposable, kept)
+    }
+}
+
+impl Drop for InnerContainer {
+    // Errors can't be reported from here, and async disposers can't be awaited, so
+    // `Container::dispose` should be preferred to rely on this.
+    fn drop(&mut self) {
+        let (disposable, _skipped) = self.take_disposable(false);
+        for (_, resolved, disposer) in disposable {
+            if let AnyDisposer::Sync(dispose) = disposer {
+                let _ = dispose(&**resolved);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        for key in _skipped {
+            tracing::warn!(key = %key, "dropped without awaiting its async disposer");
+        }
+    }
+}
+
+/// Lock `mutex`, even if a thread panicked while holding it. A panic can't leave the data the
+/// container guards with its mutexes half updated, so there's no reason to give up on it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn provider_panicked(key: Key, payload: Box<dyn Any + Send>) -> Error {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast_ref::<&'static str>() {
+            Some(message) => (*message).to_owned(),
+            None => "Box<dyn Any>".to_owned(),
+        },
+    };
+    Error::ProviderPanicked {
+        key: key.to_string(),
+        message,
+    }
+}
+
+/// Run the provider for `key`, turning a panic into an `Error::ProviderPanicked`.
+fn catch_panic<T>(key: Key, provide: impl FnOnce() -> Result<Arc<T>>) -> Result<Arc<T>>
+where
+    T: Inject + ?Sized,
+{
+    panic::catch_unwind(AssertUnwindSafe(provide))
+        .unwrap_or_else(|payload| Err(provider_panicked(key, payload)))
+}
+
+/// Polls the future returned by the async provider for `key`, turning a panic into an
+/// `Error::ProviderPanicked`.
+struct CatchPanic<'a, T: ?Sized> {
+    key: Key,
+    future: BoxFuture<'a, Result<Arc<T>>>,
+}
+
+impl<T> Future for CatchPanic<'_, T>
+where
+    T: Inject + ?Sized,
+{
+    type Output = Result<Arc<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let key = self.key;
+        let future = self.future.as_mut();
+        panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx)))
+            .unwrap_or_else(|payload| Poll::Ready(Err(provider_panicked(key, payload))))
+    }
+}
+
+/// Marks `key` as being constructed by the current task. Dropping it, whether construction
+/// finished, failed, or the future was cancelled, wakes any task waiting on the same key.
+/// A construction awaited by `Container::resolve_async`.
+#[derive(Debug)]
+struct InFlight {
+    // The address of the frame the provider is running in.
+    owner: usize,
+    // The tasks waiting on the construction to finish.
+    wakers: Vec<Waker>,
+}
+
+struct InFlightGuard<'a> {
+    container: &'a Container,
+    key: Key,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        let in_flight = lock(&self.container.inner.in_flight).remove(&self.key);
+        for waker in in_flight.into_iter().flat_map(|in_flight| in_flight.wakers) {
+            waker.wake();
+        }
+    }
+}
+
+/// Resolves once the construction of `key` that was in flight is done, or fails with
+/// `Error::Cycle` if that construction (transitively) awaits one on `container`'s resolution
+/// path, which would never finish.
+struct WaitInFlight<'a> {
+    container: &'a Container,
+    key: Key,
+    // The address of the frame entered for `key`, which identifies this wait.
+    frame: usize,
+    // The frame of the construction this waits on, once registered in `WAITS`.
+    owner: Option<usize>,
+}
+
+impl Future for WaitInFlight<'_> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut in_flight = lock(&self.container.inner.in_flight);
+        let construction = match in_flight.get_mut(&self.key) {
+            // A construction that started after ours finished is checked again by the caller.
+            Some(construction) if self.owner.is_none_or(|owner| owner == construction.owner) => {
+                construction
+            }
+            _ => return Poll::Ready(Ok(())),
+        };
+
+        if self.owner.is_none() {
+            let path: Vec<_> = self
+                .container
+                .frames()
+                .map(ResolutionFrame::address)
+                .collect();
+            let mut waits = lock(&WAITS);
+            // Follow what the owner's construction is waiting on, and what that is waiting on,
+            // etc.
+            let mut owners = vec![construction.owner];
+            let mut seen = vec![];
+            while let Some(owner) = owners.pop() {
+                if path.contains(&owner) {
+                    let mut path = self.container.path();
+                    path.push(self.key.to_string());
+                    return Poll::Ready(Err(Error::Cycle(path)));
+                }
+                if seen.contains(&owner) {
+                    continue;
+                }
+                seen.push(owner);
+                owners.extend(waits.iter().filter_map(|wait| match wait {
+                    Wait::Task {
+                        path, owner: next, ..
+                    } if path.contains(&owner) => Some(*next),
+                    _ => None,
+                }));
+            }
+            waits.push(Wait::Task {
+                frame: self.frame,
+                path,
+                owner: construction.owner,
+            });
+            let owner = construction.owner;
+            self.owner = Some(owner);
+        }
+
+        let construction = in_flight.get_mut(&self.key).unwrap();
+        if !construction.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            construction.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for WaitInFlight<'_> {
+    fn drop(&mut self) {
+        if self.owner.is_some() {
+            let frame = self.frame;
+            lock(&WAITS).retain(|wait| !matches!(wait, Wait::Task { frame: f, .. } if *f == frame));
+        }
+    }
+}
+
+/// A struct that manages all injected types.
+#[derive(Debug)]
+pub struct Container {
+    inner: Arc<InnerContainer>,
+    // The keys whose providers were running on this resolution path when this container was
+    // handed to a provider, or cloned from one that was. Only the frames still running count.
+    chain: Option<Arc<ResolutionFrame>>,
+    // The module whose provider this container was handed to, if any.
+    module: Option<&'static str>,
+}
+
+impl Clone for Container {
+    // A clone keeps the resolution path, so that a provider resolving through a clone is still
+    // checked for cycles. Frames stop counting once their provider returns, so a clone that
+    // outlives the provider call it was made in doesn't report cycles that aren't there.
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            chain: self.chain.clone(),
+            module: self.module,
+        }
+    }
+}
+
+/// The keys a registration's provider resolves, as reported by its provider.
+#[derive(Clone, Copy, Default)]
+struct Dependencies {
+    // `None` if the provider didn't say what it depends on.
+    required: Option<&'static [&'static str]>,
+    optional: &'static [&'static str],
+}
+
+impl fmt::Debug for Dependencies {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.required {
+            Some(required) => write!(f, "{:?}", required)?,
+            None => f.write_str("unknown")?,
+        }
+        if !self.optional.is_empty() {
+            write!(f, " + optional {:?}", self.optional)?;
+        }
+        Ok(())
+    }
+}
+
+/// A key whose provider is currently running, linked to the frame of the key that requested it.
+#[derive(Debug)]
+struct ResolutionFrame {
+    key: Key,
+    kind: RegistrationKind,
+    // The address of the container running the provider.
+    container: usize,
+    parent: Option<Arc<ResolutionFrame>>,
+    // Cleared once the provider returns. Clones of the container, and `Lazy`s and `Factory`s
+    // created from it, can keep the frame around for longer.
+    running: AtomicBool,
+}
+
+impl ResolutionFrame {
+    fn address(&self) -> usize {
+        self as *const ResolutionFrame as usize
+    }
+}
+
+/// The container handed to the provider of the key on top of its resolution path. The key's
+/// frame stops counting as running when this is dropped, once the provider has returned.
+struct Entered(Container);
+
+impl Entered {
+    fn frame_address(&self) -> usize {
+        self.0.chain.as_deref().map_or(0, ResolutionFrame::address)
+    }
+}
+
+impl Deref for Entered {
+    type Target = Container;
+
+    fn deref(&self) -> &Container {
+        &self.0
+    }
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        if let Some(frame) = &self.0.chain {
+            frame.running.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// Possible errors generated when running [`Container::analyze`].
+///
+/// [`Container::analyze`]: struct.Container.html#method.analyze
+#[cfg(feature = "debug")]
+#[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
+#[derive(Debug, thiserror::Error)]
+pub enum AnalysisError {
+    // FIXME(pfaria), it would be better if we could trace the
+    // entire cycle and store a Vec<String> here. Might require
+    // manually calling petgraph::visit::depth_first_search
+    /// There is a cyclic dependency within the container
+    #[error("Cycle detected at node `{0}`")]
+    Cycle(&'static str),
+    /// There is a missing dependency. Param 0 depends on Param 1, and Param 1 is missing.
+    #[error("Node `{0}` depends on `{1}`, the latter of which is not registered")]
+    Missing(&'static str, &'static str),
+    /// A singleton depends on a scoped registration, either directly or through transient
+    /// ones, so it would keep the instance of whichever scope first constructed it.
+    #[error("Singleton `{consumer}` depends on scoped `{dependency}`")]
+    CaptiveDependency {
+        /// The singleton.
+        consumer: &'static str,
+        /// The scoped registration it depends on.
+        dependency: &'static str,
+    },
+}
+
+/// A `CaptiveDependency` for every scoped registration a singleton reaches, either directly or
+/// through transient registrations and collections, which are constructed along with it.
+#[cfg(feature = "debug")]
+fn captive_dependencies(graph: &DiGraph<AnalysisNode, AnalysisEdge>) -> Vec<AnalysisError> {
+    let singletons = graph.node_indices().filter(|i| {
+        matches!(
+            graph[*i].kind,
+            NodeKind::Registration(RegistrationKind::Singleton)
+        )
+    });
+    let mut errors = vec![];
+    for singleton in singletons {
+        let mut seen = HashSet::default();
+        let mut stack = vec![singleton];
+        while let Some(node) = stack.pop() {
+            for dependency in graph.neighbors(node) {
+                if !seen.insert(dependency) {
+                    continue;
+                }
+                match graph[dependency].kind {
+                    NodeKind::Registration(RegistrationKind::Scoped) => {
+                        errors.push(AnalysisError::CaptiveDependency {
+                            consumer: graph[singleton].id.name(),
+                            dependency: graph[dependency].id.name(),
+                        })
+                    }
+                    NodeKind::Registration(RegistrationKind::Transient)
+                    | NodeKind::Decorator(_)
+                    | NodeKind::Collection => stack.push(dependency),
+                    _ => {}
+                }
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(feature = "debug")]
+#[derive(Clone, Copy)]
+enum NodeKind {
+    Registration(RegistrationKind),
+    // The layer of a decorator around a registration, starting at 1 for the innermost one.
+    Decorator(usize),
+    Instance,
+    Collection,
+    Missing,
+}
+
+#[cfg(feature = "debug")]
+#[derive(Clone)]
+struct AnalysisNode {
+    kind: NodeKind,
+    id: Key,
+}
+
+#[cfg(feature = "debug")]
+impl fmt::Display for AnalysisNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            NodeKind::Registration(reg) => match reg {
+                RegistrationKind::Transient => write!(f, "Transient - {}", self.id),
+                RegistrationKind::Singleton => write!(f, "Singleton - {}", self.id),
+                RegistrationKind::Scoped => write!(f, "Scoped - {}", self.id),
+            },
+            NodeKind::Decorator(layer) => write!(f, "Decorator {} - {}", layer, self.id),
+            NodeKind::Instance => write!(f, "Instance - {}", self.id),
+            NodeKind::Collection => write!(f, "Collection - {}", self.id),
+            NodeKind::Missing => write!(f, "MISSING - {}", self.id),
+        }
+    }
+}
+
+#[cfg(feature = "debug")]
+#[derive(Clone, Copy, Default)]
+struct AnalysisEdge {
+    // Whether the consumer can be constructed without the dependency, e.g. an
+    // `Option<Arc<T>>` field.
+    optional: bool,
+}
+
+#[cfg(feature = "debug")]
+impl fmt::Display for AnalysisEdge {
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl Container {
+    fn new(container: InnerContainer) -> Self {
+        Self {
+            inner: Arc::new(container),
+            chain: None,
+            module: None,
+        }
+    }
+
+    /// This container, continuing the resolution path of `other`. Used when the search for a
+    /// key moves on to a parent container.
+    fn continuing(&self, other: &Container) -> Container {
+        Container {
+            inner: Arc::clone(&self.inner),
+            chain: other.chain.clone(),
+            module: other.module,
+        }
+    }
+
+    /// The container to pass to the provider of `key`, with `key` pushed onto the resolution
+    /// path. Fails if `key` is already on that path, since resolving it would never finish.
+    fn enter(&self, key: Key, entry: &RegistrationEntry) -> Result<Entered> {
+        if self.frames().any(|frame| frame.key == key) {
+            let mut path = self.path();
+            path.push(key.to_string());
+            return Err(Error::Cycle(path));
+        }
+
+        Ok(Entered(Container {
+            inner: Arc::clone(&self.inner),
+            chain: Some(Arc::new(ResolutionFrame {
+                key,
+                kind: entry.registration.kind,
+                container: self.inner.address(),
+                parent: self.chain.clone(),
+                running: AtomicBool::new(true),
+            })),
+            module: entry.module.map(|entry| entry.module),
+        }))
+    }
+
+    /// Fails if `entry` is scoped and a singleton owned by a container above the scope that
+    /// holds the scoped instance is being constructed on the resolution path, when
+    /// `registrations` check for captive dependencies. The instance would otherwise outlive its
+    /// scope. The root container isn't a scope, so its singletons are also checked against the
+    /// scoped instances it holds itself, while singletons registered with
+    /// [`Container::scoped_with`] can use the scoped instances of their own container, since
+    /// both live exactly as long as it. Already resolved instances are checked too, so the
+    /// result doesn't depend on what happened to be resolved first.
+    ///
+    /// [`Container::scoped_with`]: struct.Container.html#method.scoped_with
+    fn check_captive(
+        &self,
+        registrations: &Registrations,
+        key: Key,
+        entry: &RegistrationEntry,
+    ) -> Result<()> {
+        if !registrations.check_captive
+            || !matches!(entry.registration.kind, RegistrationKind::Scoped)
+        {
+            return Ok(());
+        }
+
+        let holder = &*self.inner;
+        let consumer = self
+            .frames()
+            .filter(|frame| matches!(frame.kind, RegistrationKind::Singleton))
+            .find(|frame| {
+                match holder
+                    .ancestors()
+                    .position(|container| container.address() == frame.container)
+                {
+                    Some(0) => holder.parent.is_none(),
+                    Some(_) => true,
+                    None => false,
+                }
+            });
+        match consumer {
+            Some(consumer) => {
+                let mut path = self.path();
+                path.push(key.to_string());
+                Err(Error::CaptiveDependency {
+                    consumer: consumer.key.to_string(),
+                    dependency: key.to_string(),
+                    path,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The key a provider means by `key`. Plain names refer to a key registered by the
+    /// provider's own module if there is one, and to the rest of the container otherwise.
+    fn local(&self, key: Key) -> Key {
+        if let (Key::Name(name), Some(module)) = (key, self.module) {
+            let local = Key::Module(module, name);
+            if self.is_registered(local) {
+                return local;
+            }
+        }
+        key
+    }
+
+    /// Whether the key of `entry` can be resolved from here. Keys a module didn't export can
+    /// only be resolved by that module's own providers.
+    fn is_visible(&self, entry: &RegistrationEntry) -> bool {
+        match entry.module {
+            Some(entry) => entry.exported || self.module == Some(entry.module),
+            None => true,
+        }
+    }
+
+    fn frames(&self) -> impl Iterator<Item = &ResolutionFrame> {
+        std::iter::successors(self.chain.as_deref(), |frame| frame.parent.as_deref())
+            .filter(|frame| frame.running.load(Ordering::Acquire))
+    }
+
+    /// The keys currently being resolved, outermost first.
+    fn path(&self) -> Vec<String> {
+        let mut path: Vec<_> = self.frames().map(|frame| frame.key.to_string()).collect();
+        path.reverse();
+        path
+    }
+
+    fn key_not_found<T>(&self, key: Key) -> Error
+    where
+        T: Inject + ?Sized,
+    {
+        let mut path = self.path();
+        path.push(key.to_string());
+        Error::KeyNotFound {
+            key: key.to_string(),
+            type_name: std::any::type_name::<T>(),
+            path,
+        }
+    }
+
+    fn type_mismatch<T>(&self, key: Key) -> Error
+    where
+        T: Inject + ?Sized,
+    {
+        let mut path = self.path();
+        path.push(key.to_string());
+        Error::TypeMismatch {
+            key: key.to_string(),
+            type_name: std::any::type_name::<T>(),
+            path,
+        }
+    }
+
+    fn downcast_resolved<T>(&self, key: Key, resolved: &Resolved) -> Result<Arc<T>>
+    where
+        T: Inject + ?Sized,
+    {
+        resolved
+            .downcast_ref::<Arc<T>>()
+            .map(Arc::clone)
+            .ok_or_else(|| self.type_mismatch::<T>(key))
+    }
+
+    /// Resolve an `Arc<T>` whose provider was previously registered with `key`.
+    ///
+    /// For scoped and singleton registrations, the provider runs at most once per container.
+    /// Concurrent resolutions of the same key block until it finishes and then share its result.
+    #[inline]
+    pub fn resolve<T>(&self, key: &'static str) -> Result<Arc<T>>
+    where
+        T: Inject + ?Sized,
+    {
+        self.resolve_key::<T>(Key::named(key))
+    }
+
+    /// Resolve an `Arc<T>` whose provider was previously registered for the type `T` with
+    /// [`ContainerBuilder::register_type_as`].
+    ///
+    /// [`ContainerBuilder::register_type_as`]: struct.ContainerBuilder.html#method.register_type_as
+    #[inline]
+    pub fn resolve_type<T>(&self) -> Result<Arc<T>>
+    where
+        T: Inject + ?Sized,
+    {
+        self.resolve_key::<T>(Key::of::<T>())
+    }
+
+    /// Resolve every `Arc<T>` whose provider was registered under `key` with
+    /// [`ContainerBuilder::register_all_as`], in registration order.
+    ///
+    /// Each provider is resolved according to its own registration kind, so a collection can
+    /// mix singleton, scoped and transient providers. Fails with [`Error::KeyNotFound`] if no
+    /// provider was registered under `key`.
+    ///
+    /// ```rust
+    /// use coi::{container, ContainerBuilder, Inject};
+    /// # use std::sync::Arc;
+    ///
+    /// trait Handler: Inject {
+    ///     fn handle(&self) -> &'static str;
+    /// }
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides dyn Handler with Ping)]
+    /// struct Ping;
+    ///
+    /// impl Handler for Ping {
+    ///     fn handle(&self) -> &'static str {
+    ///         "ping"
+    ///     }
+    /// }
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides dyn Handler with Echo)]
+    /// struct Echo;
+    ///
+    /// impl Handler for Echo {
+    ///     fn handle(&self) -> &'static str {
+    ///         "echo"
+    ///     }
+    /// }
+    ///
+    /// let container = ContainerBuilder::new()
+    ///     .register_all("handlers", PingProvider)
+    ///     .register_all("handlers", EchoProvider)
+    ///     .build();
+    /// let handlers = container.resolve_all::<dyn Handler>("handlers").unwrap();
+    /// let handled: Vec<_> = handlers.iter().map(|h| h.handle()).collect();
+    /// assert_eq!(handled, ["ping", "echo"]);
+    /// ```
+    ///
+    /// [`ContainerBuilder::register_all_as`]: struct.ContainerBuilder.html#method.register_all_as
+    /// [`Error::KeyNotFound`]: enum.Error.html#variant.KeyNotFound
+    pub fn resolve_all<T>(&self, key: &'static str) -> Result<Vec<Arc<T>>>
+    where
+        T: Inject + ?Sized,
+    {
+        let count = self.inner.registrations.collection(key);
+        match count {
+            Some(count) => (0..count)
+                .map(|index| self.resolve_key::<T>(Key::Item(key, index)))
+                .collect(),
+            None => Err(self.key_not_found::<T>(Key::Name(key))),
+        }
+    }
+
+    /// Create a [`Lazy`] that resolves `key` from this container the first time it's used.
+    ///
+    /// [`Lazy`]: struct.Lazy.html
+    #[inline]
+    pub fn lazy<T>(&self, key: &'static str) -> Lazy<T>
+    where
+        T: Inject + ?Sized,
+    {
+        Lazy {
+            handle: Handle::new(self, Key::named(key)),
+            value: OnceLock::new(),
+        }
+    }
+
+    /// Create a [`Lazy`] that resolves the type `T` from this container the first time it's used.
+    ///
+    /// [`Lazy`]: struct.Lazy.html
+    #[inline]
+    pub fn lazy_type<T>(&self) -> Lazy<T>
+    where
+        T: Inject + ?Sized,
+    {
+        Lazy {
+            handle: Handle::new(self, Key::of::<T>()),
+            value: OnceLock::new(),
+        }
+    }
+
+    /// Create a [`Factory`] that resolves `key` from this container on every call.
+    ///
+    /// [`Factory`]: struct.Factory.html
+    #[inline]
+    pub fn factory<T>(&self, key: &'static str) -> Factory<T>
+    where
+        T: Inject + ?Sized,
+    {
+        Factory {
+            handle: Handle::new(self, Key::named(key)),
+            _output: PhantomData,
+        }
+    }
+
+    /// Create a [`Factory`] that resolves the type `T` from this container on every call.
+    ///
+    /// [`Factory`]: struct.Factory.html
+    #[inline]
+    pub fn factory_type<T>(&self) -> Factory<T>
+    where
+        T: Inject + ?Sized,
+    {
+        Factory {
+            handle: Handle::new(self, Key::of::<T>()),
+            _output: PhantomData,
+        }
+    }
+
+    /// Resolve an `Arc<T>` whose provider was previously registered with `key`, or `None` if no
+    /// provider was registered with `key`.
+    ///
+    /// Unlike matching on the [`Error::KeyNotFound`] returned by [`resolve`], errors from the
+    /// provider itself, including its own missing dependencies, are still returned as errors.
+    ///
+    /// ```rust
+    /// use coi::{ContainerBuilder, Inject};
+    ///
+    /// trait Metrics: Inject {}
+    ///
+    /// let container = ContainerBuilder::new().build();
+    /// let metrics = container.try_resolve::<dyn Metrics>("metrics").unwrap();
+    /// assert!(metrics.is_none());
+    /// ```
+    ///
+    /// [`Error::KeyNotFound`]: enum.Error.html#variant.KeyNotFound
+    /// [`resolve`]: #method.resolve
+    #[inline]
+    pub fn try_resolve<T>(&self, key: &'static str) -> Result<Option<Arc<T>>>
+    where
+        T: Inject + ?Sized,
+    {
+        self.try_resolve_key::<T>(Key::named(key))
+    }
+
+    /// Resolve an `Arc<T>` whose provider was previously registered for the type `T`, or `None`
+    /// if no provider was registered for it. See [`try_resolve`].
+    ///
+    /// [`try_resolve`]: #method.try_resolve
+    #[inline]
+    pub fn try_resolve_type<T>(&self) -> Result<Option<Arc<T>>>
+    where
+        T: Inject + ?Sized,
+    {
+        self.try_resolve_key::<T>(Key::of::<T>())
+    }
+
+    fn try_resolve_key<T>(&self, key: Key) -> Result<Option<Arc<T>>>
+    where
+        T: Inject + ?Sized,
+    {
+        let key = self.local(key);
+        if self.is_registered(key) {
+            self.resolve_key::<T>(key).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Whether a provider was registered with `key`, either in this container or in one of its
+    /// parents. Keys a module doesn't export aren't included.
+    ///
+    /// ```rust
+    /// use coi::{container, Inject};
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Config with Config)]
+    /// struct Config;
+    ///
+    /// let container = container! {
+    ///     config => ConfigProvider,
+    /// };
+    /// assert!(container.contains("config"));
+    /// assert!(!container.scoped().contains("metrics"));
+    /// ```
+    #[inline]
+    pub fn contains(&self, key: &'static str) -> bool {
+        self.is_registered(self.local(Key::named(key)))
+    }
+
+    /// Whether a provider was registered for the type `T`. See [`contains`].
+    ///
+    /// [`contains`]: #method.contains
+    #[inline]
+    pub fn contains_type<T>(&self) -> bool
+    where
+        T: Inject + ?Sized,
+    {
+        self.is_registered(self.local(Key::of::<T>()))
+    }
+
+    /// Every registration that can be resolved from this container, including the ones of its
+    /// parents that it didn't replace, sorted by key. Each provider of a collection is listed
+    /// under its own `key[index]`.
+    ///
+    /// ```rust
+    /// use coi::{container, Inject, RegistrationKind};
+    /// use std::sync::Arc;
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Config with Config)]
+    /// struct Config;
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Server with Server { config })]
+    /// struct Server {
+    ///     #[coi(inject)]
+    ///     config: Arc<Config>,
+    /// }
+    ///
+    /// let container = container! {
+    ///     config => ConfigProvider; singleton,
+    ///     server => ServerProvider,
+    /// };
+    /// let registrations = container.registrations();
+    /// assert_eq!(registrations[0].key, "config");
+    /// assert!(matches!(registrations[0].kind, RegistrationKind::Singleton));
+    /// assert_eq!(registrations[1].key, "server");
+    /// assert!(registrations[1].type_name.ends_with("Server"));
+    /// assert_eq!(registrations[1].dependencies, Some(&["config"][..]));
+    /// ```
+    pub fn registrations(&self) -> Vec<RegistrationInfo> {
+        let mut registrations = self
+            .inner
+            .registrations
+            .entries()
+            .into_iter()
+            .filter(|(_, _, entry)| self.is_visible(entry))
+            .map(|(key, layer, entry)| {
+                let deps = layer.dependency_map.get(&key).copied().unwrap_or_default();
+                RegistrationInfo {
+                    key: key.to_string(),
+                    kind: entry.registration.kind,
+                    type_name: entry.type_name,
+                    dependencies: deps.required,
+                    optional_dependencies: deps.optional,
+                }
+            })
+            .collect::<Vec<_>>();
+        registrations.sort_by(|a, b| a.key.cmp(&b.key));
+        registrations
+    }
+
+    /// The keys of the instances this container currently holds, sorted. Singletons are held by
+    /// the container they were registered with, so a scope only lists its scoped instances, and
+    /// a container only lists the singletons once they've been resolved or registered with
+    /// [`ContainerBuilder::register_instance`].
+    ///
+    /// ```rust
+    /// use coi::{container, Inject};
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Config with Config)]
+    /// struct Config;
+    ///
+    /// let container = container! {
+    ///     config => ConfigProvider; singleton,
+    ///     request => ConfigProvider; scoped,
+    /// };
+    /// assert!(container.resolved_keys().is_empty());
+    ///
+    /// let scoped = container.scoped();
+    /// scoped.resolve::<Config>("config").unwrap();
+    /// scoped.resolve::<Config>("request").unwrap();
+    /// assert_eq!(container.resolved_keys(), ["config"]);
+    /// assert_eq!(scoped.resolved_keys(), ["request"]);
+    /// ```
+    ///
+    /// [`ContainerBuilder::register_instance`]: struct.ContainerBuilder.html#method.register_instance
+    pub fn resolved_keys(&self) -> Vec<String> {
+        let inner = &*self.inner;
+        let mut keys = inner
+            .registrations
+            .entries()
+            .into_iter()
+            .filter(|(_, layer, entry)| {
+                self.is_visible(entry)
+                    && inner.resolves(layer, entry)
+                    && inner.resolved(entry).is_some()
+            })
+            .map(|(key, ..)| key.to_string())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    /// Whether `key` can be resolved from this container or one of its parents.
+    fn is_registered(&self, key: Key) -> bool {
+        match self.inner.registrations.get(key) {
+            Some((_, entry)) => self.is_visible(entry),
+            None => false,
+        }
+    }
+
+    fn resolve_key<T>(&self, key: Key) -> Result<Arc<T>>
+    where
+        T: Inject + ?Sized,
+    {
+        let key = self.local(key);
+        let container = &*self.inner;
+        let entry = match container.registrations.get(key) {
+            Some((registrations, entry)) if self.is_visible(entry) => {
+                if let (false, Some(parent)) =
+                    (container.resolves(registrations, entry), &container.parent)
+                {
+                    return parent.continuing(self).resolve_key::<T>(key);
+                }
+                Some(entry)
+            }
+            _ => None,
+        };
+
+        let observers = &container.registrations.observers;
+        observers.started(key);
+        #[cfg(feature = "tracing")]
+        let span = resolve_span::<T>(key, entry);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let resolved = match entry {
+            Some(entry) => self.resolve_entry::<T>(key, entry),
+            None => Err(self.key_not_found::<T>(key)),
+        };
+        observers.failed(key, resolved)
+    }
+
+    /// Resolve `key`, registered with `entry`, from this container.
+    fn resolve_entry<T>(&self, key: Key, entry: &RegistrationEntry) -> Result<Arc<T>>
+    where
+        T: Inject + ?Sized,
+    {
+        let container = &*self.inner;
+        let observers = &container.registrations.observers;
+        self.check_captive(&container.registrations, key, entry)?;
+
+        // If we already have a resolved version, return it.
+        if let Some(resolved) = &*container.resolved(entry) {
+            observers.cache_hit(key, entry);
+            return self.downcast_resolved::<T>(key, resolved);
+        }
+
+        let provider = match entry.registration.any_provider::<T>() {
+            Some(AnyProvider::Sync(provider)) => provider,
+            Some(AnyProvider::Async(_)) => return Err(Error::RequiresAsync(key.to_string())),
+            None if entry.with_args => return Err(Error::RequiresArguments(key.to_string())),
+            None => return Err(self.type_mismatch::<T>(key)),
+        };
+        // Check for cycles before waiting on a construction in progress, as that construction
+        // might be our own.
+        let entered = self.enter(key, entry)?;
+
+        match container.slot(entry) {
+            None => {
+                let timer = observers.timer();
+                let provided = catch_panic(key, || provider.provide(&entered))?;
+                observers.provided(key, entry, timer);
+                Ok(provided)
+            }
+            Some(slot) => {
+                // Only one resolution runs the provider. Any other resolution of the same key
+                // blocks here until it's done, and then uses the instance it constructed.
+                let _claim = match slot.claim() {
+                    Claim::Claimed(guard) => guard,
+                    Claim::Cached(resolved) => {
+                        observers.cache_hit(key, entry);
+                        return self.downcast_resolved::<T>(key, &resolved);
+                    }
+                    Claim::Cycle => {
+                        let mut path = self.path();
+                        path.push(key.to_string());
+                        return Err(Error::Cycle(path));
+                    }
+                };
+
+                let timer = observers.timer();
+                let provided = catch_panic(key, || provider.provide(&entered))?;
+                observers.provided(key, entry, timer);
+                let resolved = container.insert_resolved(key, entry, Box::new(provided));
+                Ok(resolved.downcast_ref::<Arc<T>>().map(Arc::clone).unwrap())
+            }
+        }
+    }
+
+    /// Resolve an `Arc<T>` whose provider was registered with `key` through
+    /// [`ContainerBuilder::register_with`], passing it `args`.
+    ///
+    /// The provider runs on every call, as its instances depend on the arguments they were
+    /// constructed with. Fails with [`Error::TypeMismatch`] if `key` wasn't registered with a
+    /// provider for this `T` and `Args`.
+    ///
+    /// ```rust
+    /// use coi::{Container, ContainerBuilder, Inject, ProvideWith};
+    /// use std::sync::Arc;
+    ///
+    /// # #[derive(Inject)]
+    /// # #[coi(provides Db with Db)]
+    /// # struct Db;
+    /// struct Report {
+    ///     db: Arc<Db>,
+    ///     user_id: u64,
+    /// }
+    ///
+    /// impl Inject for Report {}
+    ///
+    /// struct ReportProvider;
+    ///
+    /// impl ProvideWith<u64> for ReportProvider {
+    ///     type Output = Report;
+    ///
+    ///     fn provide_with(&self, container: &Container, user_id: u64) -> coi::Result<Arc<Report>> {
+    ///         let db = container.resolve::<Db>("db")?;
+    ///         Ok(Arc::new(Report { db, user_id }))
+    ///     }
+    /// }
+    ///
+    /// let container = ContainerBuilder::new()
+    ///     .register("db", DbProvider)
+    ///     .register_with("report", ReportProvider)
+    ///     .build();
+    /// let report = container.resolve_with::<Report, u64>("report", 42).unwrap();
+    /// assert_eq!(report.user_id, 42);
+    /// ```
+    ///
+    /// [`ContainerBuilder::register_with`]: struct.ContainerBuilder.html#method.register_with
+    /// [`Error::TypeMismatch`]: enum.Error.html#variant.TypeMismatch
+    pub fn resolve_with<T, Args>(&self, key: &'static str, args: Args) -> Result<Arc<T>>
+    where
+        T: Inject + ?Sized,
+        Args: 'static,
+    {
+        let key = self.local(Key::named(key));
+        let entry = match self.inner.registrations.get(key) {
+            Some((_, entry)) if self.is_visible(entry) => Some(entry),
+            _ => None,
+        };
+
+        let observers = &self.inner.registrations.observers;
+        observers.started(key);
+        #[cfg(feature = "tracing")]
+        let span = resolve_span::<T>(key, entry);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let resolved = match entry {
+            Some(entry) => self.resolve_entry_with::<T, Args>(key, entry, args),
+            None => Err(self.key_not_found::<T>(key)),
+        };
+        observers.failed(key, resolved)
+    }
+
+    fn resolve_entry_with<T, Args>(
+        &self,
+        key: Key,
+        entry: &RegistrationEntry,
+        args: Args,
+    ) -> Result<Arc<T>>
+    where
+        T: Inject + ?Sized,
+        Args: 'static,
+    {
+        self.check_captive(&self.inner.registrations, key, entry)?;
+        let provider = entry
+            .registration
+            .provider
+            .downcast_ref::<Arc<dyn ProvideWith<Args, Output = T> + Send + Sync>>()
+            .ok_or_else(|| self.type_mismatch::<T>(key))?;
+        let entered = self.enter(key, entry)?;
+        let observers = &self.inner.registrations.observers;
+        let timer = observers.timer();
+        let provided = catch_panic(key, || provider.provide_with(&entered, args))?;
+        observers.provided(key, entry, timer);
+        Ok(provided)
+    }
+
+    /// Resolve an `Arc<T>` whose provider was previously registered with `key`, awaiting the
+    /// provider if it was registered through [`ContainerBuilder::register_async_as`].
+    ///
+    /// Providers registered with a plain [`Provide`] impl are also resolved through this fn, so
+    /// it can be used for any key. For scoped and singleton registrations, at most one
+    /// construction per key is in flight at a time: concurrent callers wait for it to finish
+    /// and then share its result.
+    ///
+    /// [`ContainerBuilder::register_async_as`]: struct.ContainerBuilder.html#method.register_async_as
+    /// [`Provide`]: trait.Provide.html
+    #[inline]
+    pub fn resolve_async<T>(&self, key: &'static str) -> BoxFuture<'_, Result<Arc<T>>>
+    where
+        T: Inject + ?Sized,
+    {
+        self.resolve_key_async::<T>(Key::named(key))
+    }
+
+    /// Resolve an `Arc<T>` whose provider was previously registered for the type `T`, awaiting
+    /// the provider if it was registered through [`ContainerBuilder::register_async_type_as`].
+    ///
+    /// [`ContainerBuilder::register_async_type_as`]: struct.ContainerBuilder.html#method.register_async_type_as
+    #[inline]
+    pub fn resolve_type_async<T>(&self) -> BoxFuture<'_, Result<Arc<T>>>
+    where
+        T: Inject + ?Sized,
+    {
+        self.resolve_key_async::<T>(Key::of::<T>())
+    }
+
+    /// Resolve an `Arc<T>` whose provider was previously registered with `key`, or `None` if no
+    /// provider was registered with `key`. See [`try_resolve`] and [`resolve_async`].
+    ///
+    /// [`try_resolve`]: #method.try_resolve
+    /// [`resolve_async`]: #method.resolve_async
+    #[inline]
+    pub fn try_resolve_async<T>(&self, key: &'static str) -> BoxFuture<'_, Result<Option<Arc<T>>>>
+    where
+        T: Inject + ?Sized,
+    {
+        self.try_resolve_key_async::<T>(Key::named(key))
+    }
+
+    /// Resolve an `Arc<T>` whose provider was previously registered for the type `T`, or `None`
+    /// if no provider was registered for it. See [`try_resolve`] and [`resolve_type_async`].
+    ///
+    /// [`try_resolve`]: #method.try_resolve
+    /// [`resolve_type_async`]: #method.resolve_type_async
+    #[inline]
+    pub fn try_resolve_type_async<T>(&self) -> BoxFuture<'_, Result<Option<Arc<T>>>>
+    where
+        T: Inject + ?Sized,
+    {
+        self.try_resolve_key_async::<T>(Key::of::<T>())
+    }
+
+    fn try_resolve_key_async<T>(&self, key: Key) -> BoxFuture<'_, Result<Option<Arc<T>>>>
+    where
+        T: Inject + ?Sized,
+    {
+        Box::pin(async move {
+            let key = self.local(key);
+            if self.is_registered(key) {
+                self.resolve_key_async::<T>(key).await.map(Some)
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    fn resolve_key_async<T>(&self, key: Key) -> BoxFuture<'_, Result<Arc<T>>>
+    where
+        T: Inject + ?Sized,
+    {
+        Box::pin(async move {
+            let key = self.local(key);
+            let container = &*self.inner;
+            let entry = match container.registrations.get(key) {
+                Some((registrations, entry)) if self.is_visible(entry) => {
+                    if let (false, Some(parent)) =
+                        (container.resolves(registrations, entry), &container.parent)
+                    {
+                        let parent = parent.continuing(self);
+                        return parent.resolve_key_async::<T>(key).await;
+                    }
+                    Some(entry)
+                }
+                _ => None,
+            };
+
+            let observers = &container.registrations.observers;
+            observers.started(key);
+            let resolve = async move {
+                let resolved = match entry {
+                    Some(entry) => self.resolve_entry_async::<T>(key, entry).await,
+                    None => Err(self.key_not_found::<T>(key)),
+                };
+                observers.failed(key, resolved)
+            };
+            #[cfg(feature = "tracing")]
+            let resolve = tracing::Instrument::instrument(resolve, resolve_span::<T>(key, entry));
+            resolve.await
+        })
+    }
+
+    /// Resolve `key`, registered with `entry`, from this container. See `resolve_entry`.
+    async fn resolve_entry_async<T>(&self, key: Key, entry: &RegistrationEntry) -> Result<Arc<T>>
+    where
+        T: Inject + ?Sized,
+    {
+        let container = &*self.inner;
+        let observers = &container.registrations.observers;
+        self.check_captive(&container.registrations, key, entry)?;
+
+        let kind = entry.registration.kind;
+        let (provider, entered) = loop {
+            // If we already have a resolved version, return it.
+            if let Some(resolved) = &*container.resolved(entry) {
+                observers.cache_hit(key, entry);
+                return self.downcast_resolved::<T>(key, resolved);
+            }
+
+            let provider = match entry.registration.any_provider::<T>() {
+                // Sync providers can't be awaited, so they're constructed the same way
+                // `resolve` does it.
+                Some(AnyProvider::Sync(_)) => return self.resolve_entry::<T>(key, entry),
+                Some(AnyProvider::Async(provider)) => provider,
+                None if entry.with_args => return Err(Error::RequiresArguments(key.to_string())),
+                None => return Err(self.type_mismatch::<T>(key)),
+            };
+            // Check for cycles before waiting on an in flight construction, as that
+            // construction might be our own.
+            let entered = self.enter(key, entry)?;
+            match kind {
+                RegistrationKind::Transient => break (provider, entered),
+                RegistrationKind::Scoped | RegistrationKind::Singleton => {
+                    let mut in_flight = lock(&container.in_flight);
+                    // The construction we'd otherwise wait on might have finished since we
+                    // last checked.
+                    if let Some(resolved) = &*container.resolved(entry) {
+                        observers.cache_hit(key, entry);
+                        return self.downcast_resolved::<T>(key, resolved);
+                    }
+                    if let Entry::Vacant(vacant) = in_flight.entry(key) {
+                        vacant.insert(InFlight {
+                            owner: entered.frame_address(),
+                            wakers: vec![],
+                        });
+                        break (provider, entered);
+                    }
+                }
+            }
+
+            // Someone else is constructing this key, wait for them to finish and then check
+            // again.
+            WaitInFlight {
+                container: self,
+                key,
+                frame: entered.frame_address(),
+                owner: None,
+            }
+            .await?
+        };
+
+        let guard = match kind {
+            RegistrationKind::Transient => None,
+            RegistrationKind::Scoped | RegistrationKind::Singleton => Some(InFlightGuard {
+                container: self,
+                key,
+            }),
+        };
+        let timer = observers.timer();
+        let provided = CatchPanic {
+            key,
+            future: provider.provide(&entered),
+        }
+        .await?;
+        observers.provided(key, entry, timer);
+
+        match guard {
+            None => Ok(provided),
+            Some(guard) => {
+                let resolved = container.insert_resolved(key, entry, Box::new(provided));
+                drop(guard);
+                Ok(resolved.downcast_ref::<Arc<T>>().map(Arc::clone).unwrap())
+            }
+        }
+    }
+
+    /// Produce a child container that only contains providers for scoped registrations
+    /// Any calls to resolve from the returned container can still use the `self` container
+    /// to resolve any other kinds of registrations.
+    ///
+    /// The child shares this container's registrations, so creating it only allocates the
+    /// slots its scoped instances are cached in.
+    pub fn scoped(&self) -> Container {
+        let registrations = Arc::clone(&self.inner.registrations);
+        Container::new(InnerContainer::new(
+            registrations,
+            false,
+            Some(self.clone()),
+        ))
+    }
+
+    /// Produce a child container like [`scoped`] does, with the extra registrations made by
+    /// `registrations`, e.g. the current request or the authenticated user. They're visible to
+    /// everything resolved from the scope, including this container's scoped and transient
+    /// registrations, but not to its singletons, which outlive the scope. Providers added with
+    /// [`ContainerBuilder::register_all`] are appended to this container's collections.
+    ///
+    /// ```rust
+    /// use coi::{container, Inject};
+    /// use std::sync::Arc;
+    ///
+    /// struct User(&'static str);
+    ///
+    /// impl Inject for User {}
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Greeter with Greeter { user })]
+    /// struct Greeter {
+    ///     #[coi(inject)]
+    ///     user: Arc<User>,
+    /// }
+    ///
+    /// let container = container! {
+    ///     greeter => GreeterProvider; scoped,
+    /// };
+    ///
+    /// let scope = container.scoped_with(|builder| {
+    ///     builder.register_instance("user", Arc::new(User("alice")))
+    /// });
+    /// assert_eq!(scope.resolve::<Greeter>("greeter").unwrap().user.0, "alice");
+    /// assert!(container.resolve::<Greeter>("greeter").is_err());
+    /// ```
+    ///
+    /// [`scoped`]: #method.scoped
+    /// [`ContainerBuilder::register_all`]: struct.ContainerBuilder.html#method.register_all
+    pub fn scoped_with<F>(&self, registrations: F) -> Container
+    where
+        F: FnOnce(ContainerBuilder) -> ContainerBuilder,
+    {
+        self.child(registrations(self.child_builder()))
+    }
+
+    /// Produce a child container like [`scoped`] does, where the registrations made by
+    /// `overrides` take precedence over the ones in this container. Overridden keys are resolved
+    /// from the child, even singletons this container has already resolved, while this
+    /// container is left untouched.
+    ///
+    /// Singletons of this container that depend on an overridden key, either directly or
+    /// through other registrations, are constructed again by the child as well, so that they
+    /// use the overrides. Only the dependencies providers report through
+    /// [`Provide::dependencies`] are followed: a singleton whose provider doesn't report them,
+    /// like a closure, is shared with this container like any other singleton that isn't
+    /// overridden. Use [`ContainerBuilder::override_with`] to replace a dependency everywhere
+    /// instead.
+    ///
+    /// Providers added with [`ContainerBuilder::register_all`] are appended to this
+    /// container's collections, rather than replacing them.
+    ///
+    /// ```rust
+    /// use coi::{container, Inject};
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Db with Db("postgres"))]
+    /// struct Db(&'static str);
+    ///
+    /// let container = container! {
+    ///     db => DbProvider; singleton,
+    /// };
+    /// let db = container.resolve::<Db>("db").unwrap();
+    /// assert_eq!(db.0, "postgres");
+    ///
+    /// let fake = container.with_overrides(|builder| {
+    ///     builder.register("db", |_: &coi::Container| Ok(std::sync::Arc::new(Db("in memory"))))
+    /// });
+    /// assert_eq!(fake.resolve::<Db>("db").unwrap().0, "in memory");
+    /// assert_eq!(container.resolve::<Db>("db").unwrap().0, "postgres");
+    /// ```
+    ///
+    /// [`scoped`]: #method.scoped
+    /// [`Provide::dependencies`]: trait.Provide.html#method.dependencies
+    /// [`ContainerBuilder::override_with`]: struct.ContainerBuilder.html#method.override_with
+    /// [`ContainerBuilder::register_all`]: struct.ContainerBuilder.html#method.register_all
+    pub fn with_overrides<F>(&self, overrides: F) -> Container
+    where
+        F: FnOnce(ContainerBuilder) -> ContainerBuilder,
+    {
+        let mut overrides = overrides(self.child_builder());
+        overrides.inherit_dependents(&self.inner.registrations);
+        self.child(overrides)
+    }
+
+    /// A builder for the registrations of a child container. Its collections continue from
+    /// this container's, so that registering more providers under a collection key appends to
+    /// it instead of replacing the providers that were registered first.
+    fn child_builder(&self) -> ContainerBuilder {
+        let registrations = &self.inner.registrations;
+        let mut builder = ContainerBuilder::new();
+        for layer in registrations.layers() {
+            for name in layer.collection_map.keys() {
+                let count = registrations.collection(name).unwrap_or(0);
+                builder.collection_map.insert(name, count);
+            }
+        }
+        builder
+    }
+
+    /// A child container with the registrations in `overrides`, added on top of this
+    /// container's.
+    fn child(&self, overrides: ContainerBuilder) -> Container {
+        let base = Arc::clone(&self.inner.registrations);
+        let (registrations, instances) = overrides.into_registrations(Some(base));
+        let container = InnerContainer::new(Arc::new(registrations), true, Some(self.clone()));
+        Container::new(container.with_instances(instances))
+    }
+
+    /// Dispose of the scoped and singleton instances owned by this container, most recently
+    /// created first, so that instances are disposed of before their dependencies.
+    ///
+    /// Only instances whose provider returned a [`Disposer`] are disposed of. They're removed
+    /// from the container, so resolving them again constructs new instances. Transient
+    /// instances and instances owned by a parent container are left alone. Every instance is
+    /// disposed of even if some fail, and the failures are collected into [`Error::Dispose`].
+    /// Instances with an async disposer are left in the container and reported with
+    /// [`Error::RequiresAsyncDispose`], so that [`dispose_async`] can still dispose of them.
+    ///
+    /// Dropping the last handle to a container disposes of its instances the same way, but
+    /// ignores any errors and drops instances with an async disposer without disposing of them.
+    ///
+    /// [`Disposer`]: enum.Disposer.html
+    /// [`Error::Dispose`]: enum.Error.html#variant.Dispose
+    /// [`Error::RequiresAsyncDispose`]: enum.Error.html#variant.RequiresAsyncDispose
+    /// [`dispose_async`]: #method.dispose_async
+    pub fn dispose(&self) -> Result<()> {
+        let (disposable, kept) = self.inner.take_disposable(false);
+        let mut errors: Vec<_> = disposable
+            .into_iter()
+            .filter_map(|(key, resolved, disposer)| match disposer {
+                AnyDisposer::Sync(dispose) => {
+                    dispose(&**resolved).err().map(|e| (key.to_string(), e))
+                }
+                // Left in the container by `take_disposable`.
+                AnyDisposer::Async(_) => None,
+            })
+            .collect();
+        errors.extend(kept.into_iter().map(|key| {
+            (
+                key.to_string(),
+                Error::RequiresAsyncDispose(key.to_string()),
+            )
+        }));
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Dispose(errors))
+        }
+    }
+
+    /// Dispose of the scoped and singleton instances owned by this container, awaiting any async
+    /// disposers. See [`dispose`].
+    ///
+    /// [`dispose`]: #method.dispose
+    pub fn dispose_async(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let (disposable, _) = self.inner.take_disposable(true);
+            let mut errors = vec![];
+            for (key, resolved, disposer) in disposable {
+                let disposed = match disposer {
+                    AnyDisposer::Sync(dispose) => dispose(&**resolved),
+                    AnyDisposer::Async(dispose) => dispose(resolved).await,
+                };
+                if let Err(e) = disposed {
+                    errors.push((key.to_string(), e));
+                }
+            }
+
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::Dispose(errors))
+            }
+        })
+    }
+
+    #[cfg(feature = "debug")]
+    fn dependency_graph(&self) -> DiGraph<AnalysisNode, AnalysisEdge> {
+        let registrations = &*self.inner.registrations;
+        let entries = registrations.entries();
+        let mut graph = DiGraph::<AnalysisNode, AnalysisEdge>::new();
+        let mut key_to_node = entries
+            .iter()
+            .map(|(k, _, entry)| -> (Key, NodeIndex) {
+                let kind = if entry.instance {
+                    NodeKind::Instance
+                } else {
+                    NodeKind::Registration(entry.registration.kind)
+                };
+                let n = graph.add_node(AnalysisNode { kind, id: *k });
+                (*k, n)
+            })
+            .collect::<HashMap<Key, _>>();
+        // The providers' own dependencies start from their registration's node.
+        let registration_nodes = key_to_node.clone();
+        // Each decorator is a node of its own, between the registration it wraps and the keys
+        // depending on it.
+        for (k, layer, _) in &entries {
+            let count = layer.decorator_map.get(k).copied().unwrap_or(0);
+            for layer in 1..=count {
+                let dn = graph.add_node(AnalysisNode {
+                    kind: NodeKind::Decorator(layer),
+                    id: *k,
+                });
+                graph.add_edge(dn, key_to_node[k], AnalysisEdge::default());
+                key_to_node.insert(*k, dn);
+            }
+        }
+        let collections = registrations
+            .layers()
+            .flat_map(|layer| layer.collection_map.keys().copied())
+            .collect::<HashSet<_>>();
+        // Dependencies name a collection by its key, so each one gets a node of its own that
+        // depends on all of its items. It's kept apart from a plain key with the same name, which
+        // is a different registration.
+        let mut collection_nodes = HashMap::default();
+        for name in collections {
+            let count = registrations.collection(name).unwrap_or(0);
+            let cn = graph.add_node(AnalysisNode {
+                kind: NodeKind::Collection,
+                id: Key::Name(name),
+            });
+            collection_nodes.insert(name, cn);
+            for index in 0..count {
+                graph.add_edge(
+                    cn,
+                    key_to_node[&Key::Item(name, index)],
+                    AnalysisEdge::default(),
+                );
+            }
+        }
+        for (k, layer, entry) in &entries {
+            let deps = match layer.dependency_map.get(k) {
+                Some(deps) => deps,
+                None => continue,
+            };
+            let kn = registration_nodes[k];
+            let required = deps.required.unwrap_or(&[]).iter().map(|dep| (dep, false));
+            let optional = deps.optional.iter().map(|dep| (dep, true));
+            let edges = required
+                .chain(optional)
+                .map(|(dep, optional)| {
+                    // Dependencies of a module's providers refer to the module's own keys first.
+                    let dep = match (Key::named(dep), entry.module) {
+                        (Key::Name(name), Some(entry))
+                            if key_to_node.contains_key(&Key::Module(entry.module, name)) =>
+                        {
+                            Key::Module(entry.module, name)
+                        }
+                        (dep, _) => dep,
+                    };
+                    // A name refers to a plain key when one is registered, and to the collection
+                    // with that name otherwise.
+                    let collection = match dep {
+                        Key::Name(name) => collection_nodes.get(name),
+                        _ => None,
+                    };
+                    let vn = match key_to_node.get(&dep).or(collection) {
+                        Some(vn) => *vn,
+                        None => {
+                            let vn = graph.add_node(AnalysisNode {
+                                kind: NodeKind::Missing,
+                                id: dep,
+                            });
+                            key_to_node.insert(dep, vn);
+                            vn
+                        }
+                    };
+                    (kn, vn, optional)
+                })
+                .collect::<Vec<_>>();
+            for (from, to, optional) in edges {
+                graph.add_edge(from, to, AnalysisEdge { optional });
+            }
+        }
+
+        graph
+    }
+
+    /// Run an analysis on a container and return any issues detected.
+    /// Current analysis performed:
+    /// - Missing dependencies
+    /// - Cyclic dependencies
+    /// - Singletons depending on scoped registrations, see [`AnalysisError::CaptiveDependency`]
+    ///
+    /// Only the string keys reported by [`Provide::dependencies`] are checked. Dependencies a
+    /// provider resolves by type, like the unnamed `#[coi(inject)]` fields of a derived provider,
+    /// aren't reported, so missing types and cycles through them aren't detected here.
+    ///
+    /// [`AnalysisError::CaptiveDependency`]: enum.AnalysisError.html#variant.CaptiveDependency
+    /// [`Provide::dependencies`]: trait.Provide.html#method.dependencies
+    #[cfg(feature = "debug")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
+    pub fn analyze(&self) -> std::result::Result<(), Vec<AnalysisError>> {
+        use petgraph::{visit::EdgeRef, Direction};
+        let graph = self.dependency_graph();
+        let mut errors = graph
+            .node_indices()
+            .filter(|i| matches!(graph[*i].kind, NodeKind::Missing))
+            .flat_map(|i| {
+                let to = graph[i].id.name();
+                // Missing optional dependencies are resolved as `None`.
+                graph
+                    .edges_directed(i, Direction::Incoming)
+                    .filter(|edge| !edge.weight().optional)
+                    .map(|edge| AnalysisError::Missing(graph[edge.source()].id.name(), to))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        errors.extend(captive_dependencies(&graph));
+
+        // Do any cycles exist?
+        if let Err(cycle) = toposort(&graph, None) {
+            errors.push(AnalysisError::Cycle(graph[cycle.node_id()].id.name()));
+        }
+
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Produces a dot format output that can be processed by the [graphviz] [`dot` (pdf)]
+    /// program to generate a graphical representation of the dependency graph.
+    ///
+    /// Each decorator added with [`ContainerBuilder::decorate`] is drawn as a dashed node of its
+    /// own, between the registration it wraps and the keys depending on it.
+    ///
+    /// [`ContainerBuilder::decorate`]: struct.ContainerBuilder.html#method.decorate
+    /// [graphviz]: http://graphviz.org/
+    /// [`dot` (pdf)]: https://graphviz.gitlab.io/_pages/pdf/dotguide.pdf
+    #[cfg(feature = "debug")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
+    pub fn dot_graph(&self) -> String {
+        use petgraph::dot::{Config, Dot};
+        let graph = self.dependency_graph();
+        format!(
+            "{}",
+            Dot::with_attr_getters(
+                &graph,
+                &[Config::EdgeNoLabel],
+                &|_, edge| if edge.weight().optional {
+                    String::from("style = dashed")
+                } else {
+                    String::new()
+                },
+                &|_, (_, node)| match node.kind {
+                    NodeKind::Instance => String::from("shape = box"),
+                    NodeKind::Decorator(_) => String::from("style = dashed"),
+                    _ => String::new(),
+                },
+            )
+        )
+    }
+}
+
+/// The container and key a [`Lazy`] or [`Factory`] resolves from.
+///
+/// The container is held weakly so that a singleton holding one of these doesn't keep its own
+/// container alive. The resolution path it was created on is kept, so that resolving from it
+/// while that path is still running is checked for cycles.
+///
+/// [`Lazy`]: struct.Lazy.html
+/// [`Factory`]: struct.Factory.html
+#[derive(Clone)]
+struct Handle {
+    container: Weak<InnerContainer>,
+    key: Key,
+    module: Option<&'static str>,
+    chain: Option<Arc<ResolutionFrame>>,
+}
+
+impl Handle {
+    fn new(container: &Container, key: Key) -> Self {
+        Self {
+            container: Arc::downgrade(&container.inner),
+            key,
+            module: container.module,
+            chain: container.chain.clone(),
+        }
+    }
+
+    fn resolve<T>(&self) -> Result<Arc<T>>
+    where
+        T: Inject + ?Sized,
+    {
+        let inner = self
+            .container
+            .upgrade()
+            .ok_or_else(|| Error::ContainerDropped(self.key.to_string()))?;
+        Container {
+            inner,
+            chain: self.chain.clone(),
+            module: self.module,
+        }
+        .resolve_key::<T>(self.key)
+    }
+}
+
+/// A dependency that is only resolved the first time it's used, and then cached.
+///
+/// Useful for expensive dependencies that a consumer might not need at all. Fields of this type
+/// can be injected with `#[coi(inject)]`, or created with [`Container::lazy`]. Resolving can
+/// fail, so the dependency is only reachable through [`get`].
+///
+/// `Lazy` deliberately doesn't implement `Deref`: dereferencing can't return an error, so a
+/// missing key, a cycle or a dropped container would have to panic on first use instead. Call
+/// [`get`] wherever the dependency is needed; it resolves on the first call and returns the
+/// cached instance after that.
+///
+/// # The container must outlive the first use
+///
+/// A `Lazy` only holds a weak reference to the container it was created from, so that a
+/// singleton holding one doesn't keep its own container alive. If that container has been
+/// dropped by the time the `Lazy` is first used, [`get`] fails with [`Error::ContainerDropped`].
+/// In particular, a `Lazy` injected while resolving from a temporary scope, like
+/// `container.scoped().resolve(..)`, can't resolve anything once that statement ends; keep the
+/// scope alive for as long as its instances are used.
+///
+/// ```rust
+/// use coi::{container, Inject, Lazy};
+///
+/// trait Report: Inject {
+///     fn render(&self) -> String;
+/// }
+///
+/// #[derive(Inject)]
+/// #[coi(provides dyn Report with PdfReport)]
+/// struct PdfReport;
+///
+/// impl Report for PdfReport {
+///     fn render(&self) -> String {
+///         String::from("pdf")
+///     }
+/// }
+///
+/// #[derive(Inject)]
+/// #[coi(provides Exporter with Exporter { report })]
+/// struct Exporter {
+///     #[coi(inject)]
+///     report: Lazy<dyn Report>,
+/// }
+///
+/// let container = container! {
+///     report => PdfReportProvider,
+///     exporter => ExporterProvider,
+/// };
+/// let exporter = container.resolve::<Exporter>("exporter").unwrap();
+/// // `report` is resolved here
+/// assert_eq!(exporter.report.get().unwrap().render(), "pdf");
+/// ```
+///
+/// [`Container::lazy`]: struct.Container.html#method.lazy
+/// [`get`]: #method.get
+/// [`Error::ContainerDropped`]: enum.Error.html#variant.ContainerDropped
+pub struct Lazy<T>
+where
+    T: Inject + ?Sized,
+{
+    handle: Handle,
+    value: OnceLock<Arc<T>>,
+}
+
+impl<T> Lazy<T>
+where
+    T: Inject + ?Sized,
+{
+    /// Resolve the dependency if it hasn't been resolved yet, and return it.
+    ///
+    /// Errors aren't cached, so a failed resolution is retried on the next call.
+    pub fn get(&self) -> Result<&Arc<T>> {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+
+        let value = self.handle.resolve::<T>()?;
+        Ok(self.value.get_or_init(|| value))
+    }
+}
+
+impl<T> fmt::Debug for Lazy<T>
+where
+    T: Inject + ?Sized,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Lazy")
+            .field("key", &self.handle.key)
+            .field("resolved", &self.value.get().is_some())
+            .finish()
+    }
+}
+
+/// A handle that resolves its dependency again on every call to [`create`].
+///
+/// With a transient registration, each call produces a new instance, so a consumer can construct
+/// as many as it needs long after it was itself constructed. Scoped and singleton registrations
+/// still hand out their shared instance. Fields of this type can be injected with
+/// `#[coi(inject)]`, or created with [`Container::factory`].
+///
+/// Like a [`Lazy`], a `Factory` only holds a weak reference to its container, and fails with
+/// [`Error::ContainerDropped`] once that container is gone, e.g. when it was injected while
+/// resolving from a temporary scope.
+///
+/// ```rust
+/// use coi::{container, Factory, Inject};
+/// # use std::sync::Arc;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Connection with Connection)]
+/// struct Connection;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Pool with Pool { connection })]
+/// struct Pool {
+///     #[coi(inject)]
+///     connection: Factory<Connection>,
+/// }
+///
+/// let container = container! {
+///     connection => ConnectionProvider,
+///     pool => PoolProvider,
+/// };
+/// let pool = container.resolve::<Pool>("pool").unwrap();
+/// let first = pool.connection.create().unwrap();
+/// let second = pool.connection.create().unwrap();
+/// assert!(!Arc::ptr_eq(&first, &second));
+/// ```
+///
+/// [`create`]: #method.create
+/// [`Container::factory`]: struct.Container.html#method.factory
+/// [`Lazy`]: struct.Lazy.html
+/// [`Error::ContainerDropped`]: enum.Error.html#variant.ContainerDropped
+pub struct Factory<T>
+where
+    T: Inject + ?Sized,
+{
+    handle: Handle,
+    _output: PhantomData<fn() -> Arc<T>>,
+}
+
+impl<T> Factory<T>
+where
+    T: Inject + ?Sized,
+{
+    /// Resolve the dependency.
+    pub fn create(&self) -> Result<Arc<T>> {
+        self.handle.resolve::<T>()
+    }
+}
+
+impl<T> Clone for Factory<T>
+where
+    T: Inject + ?Sized,
+{
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+            _output: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Factory<T>
+where
+    T: Inject + ?Sized,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Factory")
+            .field("key", &self.handle.key)
+            .finish()
+    }
+}
+
+/// A reusable group of registrations, added to a container with
+/// [`ContainerBuilder::add_module`].
+///
+/// Keys the module registers by name are qualified with the module's name, so that modules from
+/// different crates can't clash: `repo` registered by the `auth` module is resolved as
+/// `auth::repo`. The module's own providers can keep resolving it as `repo`, since plain names
+/// refer to their own module's keys first. Only the keys listed in [`exports`] can be resolved
+/// from outside of the module.
+///
+/// ```rust
+/// use coi::{ContainerBuilder, Inject, Module};
+/// # use std::sync::Arc;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Repo with Repo)]
+/// struct Repo;
+///
+/// #[derive(Inject)]
+/// #[coi(provides Auth with Auth { repo })]
+/// struct Auth {
+///     #[coi(inject)]
+///     repo: Arc<Repo>,
+/// }
+///
+/// struct AuthModule;
+///
+/// impl Module for AuthModule {
+///     fn name(&self) -> &'static str {
+///         "auth"
+///     }
+///
+///     fn exports(&self) -> &'static [&'static str] {
+///         &["auth"]
+///     }
+///
+///     fn register(&self, builder: ContainerBuilder) -> ContainerBuilder {
+///         builder
+///             .register("repo", RepoProvider)
+///             .register("auth", AuthProvider)
+///     }
+/// }
+///
+/// let container = ContainerBuilder::new().add_module(AuthModule).build();
+/// assert!(container.resolve::<Auth>("auth::auth").is_ok());
+/// // `repo` wasn't exported
+/// assert!(container.resolve::<Repo>("auth::repo").is_err());
+/// ```
+///
+/// Only keys registered by name are qualified. Types and collections registered by a module are
+/// shared with the rest of the container. A module can't register keys qualified with another
+/// module's name; [`ContainerBuilder::try_build`] fails with [`Error::ForeignModuleKeys`] if it
+/// tries to, and with [`Error::UnknownExports`] if it exports keys it never registered.
+///
+/// Since `::` separates a module's name from its keys, every key containing `::` is treated as
+/// qualified, whether or not modules are used: `"db::pool"` is the `pool` key of a `db` module.
+/// Containers registering and resolving such keys outside of any module keep working as before,
+/// but registering `"db::pool"` from within a module other than `db` now fails the build.
+///
+/// [`ContainerBuilder::add_module`]: struct.ContainerBuilder.html#method.add_module
+/// [`ContainerBuilder::try_build`]: struct.ContainerBuilder.html#method.try_build
+/// [`Error::ForeignModuleKeys`]: enum.Error.html#variant.ForeignModuleKeys
+/// [`Error::UnknownExports`]: enum.Error.html#variant.UnknownExports
+/// [`exports`]: #tymethod.exports
+pub trait Module {
+    /// The name this module's keys are qualified with.
+    fn name(&self) -> &'static str;
+
+    /// The keys, as registered by this module, that can be resolved from outside of it.
+    fn exports(&self) -> &'static [&'static str];
+
+    /// The names of the modules this one resolves keys from. Building the container fails with
+    /// [`Error::MissingModules`] if any of them weren't added.
+    ///
+    /// [`Error::MissingModules`]: enum.Error.html#variant.MissingModules
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Register this module's providers with `builder`.
+    fn register(&self, builder: ContainerBuilder) -> ContainerBuilder;
+}
+
+/// What a [`ContainerBuilder`] does when a key is registered more than once.
+///
+/// [`ContainerBuilder`]: struct.ContainerBuilder.html
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// The last registration replaces any earlier ones.
+    #[default]
+    Replace,
+    /// The first registration is kept, and any later ones are ignored.
+    KeepFirst,
+    /// The first registration is kept, and every later one is recorded so that
+    /// [`ContainerBuilder::try_build`] fails with [`Error::DuplicateKeys`].
+    ///
+    /// [`ContainerBuilder::try_build`]: struct.ContainerBuilder.html#method.try_build
+    /// [`Error::DuplicateKeys`]: enum.Error.html#variant.DuplicateKeys
+    Error,
+}
+
+/// A key that was registered more than once, along with where it was registered.
+#[derive(Clone, Debug)]
+pub struct DuplicateKey {
+    /// The key that was registered more than once.
+    pub key: String,
+    /// Where the registration that was kept was made.
+    pub first: &'static Location<'static>,
+    /// Where the registration that was rejected was made.
+    pub second: &'static Location<'static>,
+}
+
+impl fmt::Display for DuplicateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (registered at {} and {})",
+            self.key, self.first, self.second
+        )
+    }
+}
+
+// The name, dependencies and exports of a module.
+type ModuleInfo = (
+    &'static str,
+    &'static [&'static str],
+    &'static [&'static str],
+);
+
+/// A builder used to construct a `Container`.
+#[derive(Clone, Default)]
+pub struct ContainerBuilder {
+    provider_map: HashMap<Key, Registration<Arc<dyn Any + Send + Sync>>>,
+    duplicate_policy: DuplicatePolicy,
+    check_captive_dependencies: bool,
+    // Where the registration currently kept for each key was made.
+    locations: HashMap<Key, &'static Location<'static>>,
+    // The registrations rejected by `DuplicatePolicy::Error`.
+    duplicates: Vec<DuplicateKey>,
+    // The singletons constructed by `build_eager`, and how to resolve them without knowing
+    // their type.
+    eager_map: HashMap<Key, ResolveErased>,
+    disposer_map: HashMap<Key, AnyDisposer>,
+    collection_map: HashMap<&'static str, usize>,
+    // The name and exports of the module currently being added, if any.
+    module: Option<(&'static str, &'static [&'static str])>,
+    module_map: HashMap<Key, ModuleEntry>,
+    // The name, dependencies and exports of every module added.
+    modules: Vec<ModuleInfo>,
+    // The keys qualified with another module's name that a module tried to register.
+    foreign_keys: Vec<(String, String)>,
+    // The instances registered with `register_instance`, cached by the container when it's built.
+    instance_map: HashMap<Key, Arc<Resolved>>,
+    // The keys registered with `register_with`.
+    with_args: HashSet<Key>,
+    observers: Vec<Arc<dyn ResolveObserver>>,
+    // The name of the type each key resolves to.
+    type_names: HashMap<Key, &'static str>,
+    dependency_map: HashMap<Key, Dependencies>,
+    // The number of decorators wrapping the provider of each decorated key.
+    #[cfg(feature = "debug")]
+    decorator_map: HashMap<Key, usize>,
+}
+
+impl ContainerBuilder {
+    /// Constructor for `ContainerBuilder`.
+    pub fn new() -> Self {
+        Self {
+            provider_map: HashMap::default(),
+            duplicate_policy: DuplicatePolicy::default(),
+            check_captive_dependencies: false,
+            locations: HashMap::default(),
+            duplicates: vec![],
+            eager_map: HashMap::default(),
+            disposer_map: HashMap::default(),
+            collection_map: HashMap::default(),
+            module: None,
+            module_map: HashMap::default(),
+            modules: vec![],
+            foreign_keys: vec![],
+            instance_map: HashMap::default(),
+            with_args: HashSet::default(),
+            observers: vec![],
+            type_names: HashMap::default(),
+            dependency_map: HashMap::default(),
+            #[cfg(feature = "debug")]
+            decorator_map: HashMap::default(),
+        }
+    }
+
+    /// Add the registrations made by `module`. Keys registered by name are qualified with the
+    /// module's name, so `repo` registered by the `auth` module is resolved as `auth::repo`.
+    /// See [`Module`].
+    ///
+    /// [`Module`]: trait.Module.html
+    pub fn add_module<M>(mut self, module: M) -> Self
+    where
+        M: Module,
+    {
+        let outer = self.module.replace((module.name(), module.exports()));
+        self.modules
+            .push((module.name(), module.dependencies(), module.exports()));
+        let mut builder = module.register(self);
+        builder.module = outer;
+        builder
+    }
+
+    /// `key` qualified with the name of the module currently being added.
+    fn qualify(&self, key: Key) -> Key {
+        match (self.module, key) {
+            (Some((module, _)), Key::Name(name)) => Key::Module(module, name),
+            _ => key,
+        }
+    }
+
+    /// Remember which module registered `key`, if it was registered by one.
+    fn record_module(&mut self, key: Key) {
+        match self.module {
+            Some((module, exports)) => {
+                let exported = match key {
+                    Key::Module(m, name) if m == module => exports.contains(&name),
+                    // Keys of other modules are rejected by `admit`, so only types and
+                    // collection items, which are shared, are left.
+                    _ => true,
+                };
+                self.module_map
+                    .insert(key, ModuleEntry { module, exported });
+            }
+            None => {
+                self.module_map.remove(&key);
+            }
+        }
+    }
+
+    /// Set what happens when a key is registered more than once. Only registrations made after
+    /// this call are affected. Defaults to [`DuplicatePolicy::Replace`].
+    ///
+    /// ```rust
+    /// use coi::{ContainerBuilder, DuplicatePolicy, Error, Inject};
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Logger with Logger)]
+    /// struct Logger;
+    ///
+    /// let result = ContainerBuilder::new()
+    ///     .duplicate_policy(DuplicatePolicy::Error)
+    ///     .register("logger", LoggerProvider)
+    ///     .register("logger", LoggerProvider)
+    ///     .try_build();
+    /// match result {
+    ///     Err(Error::DuplicateKeys(duplicates)) => assert_eq!(duplicates[0].key, "logger"),
+    ///     _ => panic!("expected logger to be a duplicate"),
+    /// }
+    /// ```
+    ///
+    /// [`DuplicatePolicy::Replace`]: enum.DuplicatePolicy.html#variant.Replace
+    pub fn duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = policy;
+        self
+    }
+
+    /// Make resolving a scoped key fail with [`Error::CaptiveDependency`] while a singleton is
+    /// being constructed, whether the singleton resolves it directly or through transient
+    /// registrations. Scopes created from the container inherit the check. Off by default, as
+    /// it walks the resolution path on every scoped resolution.
+    ///
+    /// Singletons registered with [`Container::scoped_with`] live exactly as long as the scope
+    /// they were registered with, so they can resolve that scope's scoped keys. Only singletons
+    /// of the containers above it are checked.
+    ///
+    /// With the "debug" feature, [`Container::analyze`] finds the same problem from the
+    /// dependencies providers declare, without resolving anything.
+    ///
+    /// ```rust
+    /// use coi::{container, ContainerBuilder, Error, Inject, Registration, RegistrationKind};
+    /// use std::sync::Arc;
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Request with Request)]
+    /// struct Request;
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Cache with Cache { request })]
+    /// struct Cache {
+    ///     #[coi(inject)]
+    ///     request: Arc<Request>,
+    /// }
+    ///
+    /// let container = ContainerBuilder::new()
+    ///     .check_captive_dependencies(true)
+    ///     .register_as("request", Registration::new(RegistrationKind::Scoped, RequestProvider))
+    ///     .register_as("cache", Registration::new(RegistrationKind::Singleton, CacheProvider))
+    ///     .build();
+    /// match container.scoped().resolve::<Cache>("cache") {
+    ///     Err(Error::CaptiveDependency { consumer, dependency, .. }) => {
+    ///         assert_eq!(consumer, "cache");
+    ///         assert_eq!(dependency, "request");
+    ///     }
+    ///     _ => panic!("expected a captive dependency"),
+    /// }
+    /// ```
+    ///
+    /// [`Error::CaptiveDependency`]: enum.Error.html#variant.CaptiveDependency
+    /// [`Container::scoped_with`]: struct.Container.html#method.scoped_with
+    /// [`Container::analyze`]: struct.Container.html#method.analyze
+    pub fn check_captive_dependencies(mut self, check: bool) -> Self {
+        self.check_captive_dependencies = check;
+        self
+    }
+
+    /// Whether a registration for `key` made at `location` should be kept, according to the
+    /// duplicate policy. Keys of another module are never kept when registered by a module.
+    fn admit(&mut self, key: Key, location: &'static Location<'static>) -> bool {
+        if let (Some((module, _)), Key::Module(owner, _)) = (self.module, key) {
+            if owner != module {
+                self.foreign_keys.push((module.to_owned(), key.to_string()));
+                return false;
+            }
+        }
+
+        match self.locations.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(location);
+                true
+            }
+            Entry::Occupied(mut entry) => match self.duplicate_policy {
+                DuplicatePolicy::Replace => {
+                    entry.insert(location);
+                    true
+                }
+                DuplicatePolicy::KeepFirst => false,
+                DuplicatePolicy::Error => {
+                    self.duplicates.push(DuplicateKey {
+                        key: key.to_string(),
+                        first: entry.get(),
+                        second: location,
+                    });
+                    false
+                }
+            },
+        }
+    }
+
+    /// Notify `observer` of every resolution made by the container, its scopes, and the
+    /// containers derived from it with [`Container::scoped_with`] or
+    /// [`Container::with_overrides`]. Observers are called in the order they were added. See
+    /// [`ResolveObserver`].
+    ///
+    /// Singletons are resolved by the container they were registered with, so observers added
+    /// to a derived container aren't notified of the singletons it shares with its parent.
+    ///
+    /// [`Container::scoped_with`]: struct.Container.html#method.scoped_with
+    /// [`Container::with_overrides`]: struct.Container.html#method.with_overrides
+    /// [`ResolveObserver`]: trait.ResolveObserver.html
+    pub fn add_observer<O>(mut self, observer: O) -> Self
+    where
+        O: ResolveObserver + 'static,
+    {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Register a `Provider` for `T` with identifier `key`.
+    #[inline]
+    #[track_caller]
+    pub fn register<P, T>(self, key: &'static str, provider: P) -> Self
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        self.register_as(
+            key,
+            Registration::new(RegistrationKind::Transient, provider),
+        )
+    }
+
+    fn get_arc<P, T>(provider: P) -> Arc<dyn Provide<Output = T> + Send + Sync>
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        Arc::new(provider)
+    }
+
+    /// Register a `Provider` for `T` with identifier `key`, while also specifying the resolution
+    /// behavior.
+    #[inline]
+    #[track_caller]
+    pub fn register_as<P, T>(self, key: &'static str, registration: Registration<P>) -> Self
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        self.register_key_as(Key::named(key), registration)
+    }
+
+    /// Register a `Provider` for `T`, identified by the type `T` itself rather than a string key.
+    /// It can then be resolved with [`Container::resolve_type`], and keys can't clash between
+    /// unrelated crates. String keys are still needed to register more than one provider for
+    /// the same `T`.
+    ///
+    /// ```rust
+    /// use coi::{ContainerBuilder, Inject};
+    /// # use std::sync::Arc;
+    ///
+    /// trait Repo: Inject {}
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides dyn Repo with SqlRepo)]
+    /// struct SqlRepo;
+    ///
+    /// impl Repo for SqlRepo {}
+    ///
+    /// let container = ContainerBuilder::new()
+    ///     .register_type::<dyn Repo, _>(SqlRepoProvider)
+    ///     .build();
+    /// let repo: Arc<dyn Repo> = container.resolve_type::<dyn Repo>().unwrap();
+    /// ```
+    ///
+    /// [`Container::resolve_type`]: struct.Container.html#method.resolve_type
+    #[inline]
+    #[track_caller]
+    pub fn register_type<T, P>(self, provider: P) -> Self
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        self.register_type_as(Registration::new(RegistrationKind::Transient, provider))
+    }
+
+    /// Register a `Provider` for `T`, identified by the type `T` itself, while also specifying
+    /// the resolution behavior. See [`register_type`].
+    ///
+    /// [`register_type`]: #method.register_type
+    #[inline]
+    #[track_caller]
+    pub fn register_type_as<T, P>(self, registration: Registration<P>) -> Self
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        self.register_key_as(Key::of::<T>(), registration)
+    }
+
+    #[track_caller]
+    fn register_key_as<P, T>(mut self, key: Key, registration: Registration<P>) -> Self
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        let key = self.qualify(key);
+        if !self.admit(key, Location::caller()) {
+            return self;
+        }
+        self.record_module(key);
+        self.instance_map.remove(&key);
+        self.insert_registration(key, registration);
+        self
+    }
+
+    fn insert_registration<P, T>(&mut self, key: Key, registration: Registration<P>)
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        let deps = Dependencies {
+            required: registration.provider.dependencies(),
+            optional: registration.provider.optional_dependencies(),
+        };
+        match registration.provider.disposer() {
+            Some(disposer) => self.disposer_map.insert(key, AnyDisposer::new(disposer)),
+            None => self.disposer_map.remove(&key),
+        };
+        match registration.kind {
+            RegistrationKind::Singleton => self.eager_map.insert(key, resolve_erased::<T>),
+            RegistrationKind::Scoped | RegistrationKind::Transient => self.eager_map.remove(&key),
+        };
+        self.with_args.remove(&key);
+        self.provider_map.insert(
+            key,
+            Registration {
+                kind: registration.kind,
+                provider: Arc::new(Self::get_arc(registration.provider))
+                    as Arc<dyn Any + Send + Sync>,
+            },
+        );
+        self.type_names.insert(key, std::any::type_name::<T>());
+        self.dependency_map.insert(key, deps);
+        #[cfg(feature = "debug")]
+        self.decorator_map.remove(&key);
+    }
+
+    /// Register an already constructed `instance` with identifier `key`, e.g. parsed
+    /// configuration. It's resolved like a singleton, except that no provider is ever invoked
+    /// for it, and the container never disposes of it.
+    ///
+    /// ```rust
+    /// use coi::{ContainerBuilder, Inject};
+    /// use std::sync::Arc;
+    ///
+    /// struct Config {
+    ///     port: u16,
+    /// }
+    ///
+    /// impl Inject for Config {}
+    ///
+    /// let config = Arc::new(Config { port: 8080 });
+    /// let container = ContainerBuilder::new()
+    ///     .register_instance("config", Arc::clone(&config))
+    ///     .build();
+    /// let resolved = container.resolve::<Config>("config").unwrap();
+    /// assert!(Arc::ptr_eq(&config, &resolved));
+    /// assert_eq!(resolved.port, 8080);
+    /// ```
+    #[track_caller]
+    pub fn register_instance<T>(mut self, key: &'static str, instance: Arc<T>) -> Self
+    where
+        T: Inject + ?Sized,
+    {
+        let key = self.qualify(Key::named(key));
+        if !self.admit(key, Location::caller()) {
+            return self;
+        }
+        self.record_module(key);
+        self.insert_registration(
+            key,
+            Registration::new(
+                RegistrationKind::Singleton,
+                InstanceProvider(Arc::clone(&instance)),
+            ),
+        );
+        // There's nothing to construct
+        self.eager_map.remove(&key);
+        self.instance_map
+            .insert(key, Arc::new(Box::new(instance) as Resolved));
+        self
+    }
+
+    /// Replace the registration for `key` with `registration`, typically to swap a provider for
+    /// a fake in tests. Unlike registering `key` again, this fails with [`Error::KeyNotFound`]
+    /// if nothing was registered with `key`, so an override can't silently go unused once the
+    /// key it was meant for is renamed.
+    ///
+    /// ```rust
+    /// use coi::{ContainerBuilder, Inject, Registration, RegistrationKind};
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Db with Db("postgres"))]
+    /// struct Db(&'static str);
+    ///
+    /// let builder = ContainerBuilder::new().register("db", DbProvider);
+    /// let container = builder
+    ///     .override_with(
+    ///         "db",
+    ///         Registration::new(RegistrationKind::Singleton, |_: &coi::Container| {
+    ///             Ok(std::sync::Arc::new(Db("in memory")))
+    ///         }),
+    ///     )
+    ///     .unwrap()
+    ///     .build();
+    /// assert_eq!(container.resolve::<Db>("db").unwrap().0, "in memory");
+    /// ```
+    ///
+    /// [`Error::KeyNotFound`]: enum.Error.html#variant.KeyNotFound
+    #[inline]
+    #[track_caller]
+    pub fn override_with<P, T>(
+        self,
+        key: &'static str,
+        registration: Registration<P>,
+    ) -> Result<Self>
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        self.override_key_with(Key::named(key), registration)
+    }
+
+    /// Replace the registration for the type `T` with `registration`. See [`override_with`].
+    ///
+    /// [`override_with`]: #method.override_with
+    #[inline]
+    #[track_caller]
+    pub fn override_type_with<T, P>(self, registration: Registration<P>) -> Result<Self>
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        self.override_key_with(Key::of::<T>(), registration)
+    }
+
+    #[track_caller]
+    fn override_key_with<P, T>(mut self, key: Key, registration: Registration<P>) -> Result<Self>
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        let key = self.qualify(key);
+        if !self.provider_map.contains_key(&key) {
+            return Err(Error::KeyNotFound {
+                key: key.to_string(),
+                type_name: std::any::type_name::<T>(),
+                path: vec![key.to_string()],
+            });
+        }
+
+        // Overriding is deliberate, so it's never treated as a duplicate, and the key stays in
+        // the module it was registered by.
+        self.locations.remove(&key);
+        let entry = self.module_map.get(&key).copied();
+        let mut builder = self.register_key_as(key, registration);
+        if let Some(entry) = entry {
+            builder.module_map.insert(key, entry);
+        }
+        Ok(builder)
+    }
+
+    /// Wrap the provider registered with `key` in `decorator`, which is given every instance it
+    /// provides and returns the instance to resolve instead, e.g. to add caching or logging
+    /// around a `dyn Trait` without its consumers knowing. Decorating a key again wraps the
+    /// previous decorator, so the last one added is the outermost.
+    ///
+    /// The registration keeps its [`RegistrationKind`], so the decorators of a singleton run
+    /// once. Fails with [`Error::KeyNotFound`] if nothing was registered with `key`, and with
+    /// [`Error::TypeMismatch`] if it wasn't registered with a provider for `T`. Registering `key`
+    /// again afterwards replaces its decorators along with its provider.
+    ///
+    /// ```rust
+    /// use coi::{ContainerBuilder, Inject};
+    /// use std::sync::Arc;
+    ///
+    /// trait Repo: Inject {
+    ///     fn get(&self) -> String;
+    /// }
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides dyn Repo with SqlRepo)]
+    /// struct SqlRepo;
+    ///
+    /// impl Repo for SqlRepo {
+    ///     fn get(&self) -> String {
+    ///         String::from("row")
+    ///     }
+    /// }
+    ///
+    /// struct Logged(Arc<dyn Repo>);
+    ///
+    /// impl Inject for Logged {}
+    ///
+    /// impl Repo for Logged {
+    ///     fn get(&self) -> String {
+    ///         format!("logged {}", self.0.get())
+    ///     }
+    /// }
+    ///
+    /// let container = ContainerBuilder::new()
+    ///     .register("repo", SqlRepoProvider)
+    ///     .decorate::<dyn Repo, _>("repo", |inner, _| Ok(Arc::new(Logged(inner))))
+    ///     .unwrap()
+    ///     .build();
+    /// let repo = container.resolve::<dyn Repo>("repo").unwrap();
+    /// assert_eq!(repo.get(), "logged row");
+    /// ```
+    ///
+    /// [`RegistrationKind`]: enum.RegistrationKind.html
+    /// [`Error::KeyNotFound`]: enum.Error.html#variant.KeyNotFound
+    /// [`Error::TypeMismatch`]: enum.Error.html#variant.TypeMismatch
+    pub fn decorate<T, F>(self, key: &'static str, decorator: F) -> Result<Self>
+    where
+        T: Inject + ?Sized,
+        F: Fn(Arc<T>, &Container) -> Result<Arc<T>> + Send + Sync + 'static,
+    {
+        self.decorate_key(Key::named(key), decorator)
+    }
+
+    /// Wrap the provider registered for the type `T` in `decorator`. See [`decorate`].
+    ///
+    /// [`decorate`]: #method.decorate
+    pub fn decorate_type<T, F>(self, decorator: F) -> Result<Self>
+    where
+        T: Inject + ?Sized,
+        F: Fn(Arc<T>, &Container) -> Result<Arc<T>> + Send + Sync + 'static,
+    {
+        self.decorate_key(Key::of::<T>(), decorator)
+    }
+
+    fn decorate_key<T, F>(mut self, key: Key, decorator: F) -> Result<Self>
+    where
+        T: Inject + ?Sized,
+        F: Fn(Arc<T>, &Container) -> Result<Arc<T>> + Send + Sync + 'static,
+    {
+        let key = self.qualify(key);
+        let registration = match self.provider_map.get_mut(&key) {
+            Some(registration) => registration,
+            None => {
+                return Err(Error::KeyNotFound {
+                    key: key.to_string(),
+                    type_name: std::any::type_name::<T>(),
+                    path: vec![key.to_string()],
+                })
+            }
+        };
+        registration.provider = match registration.any_provider::<T>() {
+            Some(AnyProvider::Sync(inner)) => {
+                Arc::new(Self::get_arc(Decorated { inner, decorator }))
+                    as Arc<dyn Any + Send + Sync>
+            }
+            Some(AnyProvider::Async(inner)) => {
+                Arc::new(Self::get_async_arc(Decorated { inner, decorator }))
+                    as Arc<dyn Any + Send + Sync>
+            }
+            None => {
+                return Err(Error::TypeMismatch {
+                    key: key.to_string(),
+                    type_name: std::any::type_name::<T>(),
+                    path: vec![key.to_string()],
+                })
+            }
+        };
+        // A registered instance has to go through its decorators, so it's provided on first use
+        // rather than cached from the start.
+        self.instance_map.remove(&key);
+        #[cfg(feature = "debug")]
+        {
+            *self.decorator_map.entry(key).or_insert(0) += 1;
+        }
+        Ok(self)
+    }
+
+    /// Add a `Provider` for `T` to the collection identified by `key`. Unlike [`register`],
+    /// registering more than one provider under the same key keeps all of them, and they're
+    /// resolved together with [`Container::resolve_all`].
+    ///
+    /// [`register`]: #method.register
+    /// [`Container::resolve_all`]: struct.Container.html#method.resolve_all
+    #[inline]
+    #[track_caller]
+    pub fn register_all<P, T>(self, key: &'static str, provider: P) -> Self
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        self.register_all_as(
+            key,
+            Registration::new(RegistrationKind::Transient, provider),
+        )
+    }
+
+    /// Add a `Provider` for `T` to the collection identified by `key`, while also specifying the
+    /// resolution behavior. See [`register_all`].
+    ///
+    /// [`register_all`]: #method.register_all
+    #[inline]
+    #[track_caller]
+    pub fn register_all_as<P, T>(mut self, key: &'static str, registration: Registration<P>) -> Self
+    where
+        T: Inject + ?Sized,
+        P: Provide<Output = T> + Send + Sync + 'static,
+    {
+        let count = self.collection_map.entry(key).or_insert(0);
+        let index = *count;
+        *count += 1;
+        self.register_key_as(Key::Item(key, index), registration)
+    }
+
+    /// Register an `AsyncProvide`r for `T` with identifier `key`.
+    #[inline]
+    #[track_caller]
+    pub fn register_async<P, T>(self, key: &'static str, provider: P) -> Self
+    where
+        T: Inject + ?Sized,
+        P: AsyncProvide<Output = T> + Send + Sync + 'static,
+    {
+        self.register_async_as(
+            key,
+            Registration::new(RegistrationKind::Transient, provider),
+        )
+    }
+
+    fn get_async_arc<P, T>(provider: P) -> Arc<dyn AsyncProvide<Output = T> + Send + Sync>
+    where
+        T: Inject + ?Sized,
+        P: AsyncProvide<Output = T> + Send + Sync + 'static,
+    {
+        Arc::new(provider)
+    }
+
+    /// Register an `AsyncProvide`r for `T` with identifier `key`, while also specifying the
+    /// resolution behavior. Keys registered this way can only be resolved through
+    /// [`Container::resolve_async`].
+    ///
+    /// [`Container::resolve_async`]: struct.Container.html#method.resolve_async
+    #[inline]
+    #[track_caller]
+    pub fn register_async_as<P, T>(self, key: &'static str, registration: Registration<P>) -> Self
+    where
+        T: Inject + ?Sized,
+        P: AsyncProvide<Output = T> + Send + Sync + 'static,
+    {
+        self.register_async_key_as(Key::named(key), registration)
+    }
+
+    /// Register an `AsyncProvide`r for `T`, identified by the type `T` itself. See
+    /// [`register_type`].
+    ///
+    /// [`register_type`]: #method.register_type
+    #[inline]
+    #[track_caller]
+    pub fn register_async_type<T, P>(self, provider: P) -> Self
+    where
+        T: Inject + ?Sized,
+        P: AsyncProvide<Output = T> + Send + Sync + 'static,
+    {
+        self.register_async_type_as(Registration::new(RegistrationKind::Transient, provider))
+    }
+
+    /// Register an `AsyncProvide`r for `T`, identified by the type `T` itself, while also
+    /// specifying the resolution behavior. Types registered this way can only be resolved
+    /// through [`Container::resolve_type_async`].
+    ///
+    /// [`Container::resolve_type_async`]: struct.Container.html#method.resolve_type_async
+    #[inline]
+    #[track_caller]
+    pub fn register_async_type_as<T, P>(self, registration: Registration<P>) -> Self
+    where
+        T: Inject + ?Sized,
+        P: AsyncProvide<Output = T> + Send + Sync + 'static,
+    {
+        self.register_async_key_as(Key::of::<T>(), registration)
+    }
+
+    #[track_caller]
+    fn register_async_key_as<P, T>(mut self, key: Key, registration: Registration<P>) -> Self
+    where
+        T: Inject + ?Sized,
+        P: AsyncProvide<Output = T> + Send + Sync + 'static,
+    {
+        let key = self.qualify(key);
+        if !self.admit(key, Location::caller()) {
+            return self;
+        }
+        self.record_module(key);
+        self.instance_map.remove(&key);
+        let deps = Dependencies {
+            required: registration.provider.dependencies(),
+            optional: registration.provider.optional_dependencies(),
+        };
+        match registration.provider.disposer() {
+            Some(disposer) => self.disposer_map.insert(key, AnyDisposer::new(disposer)),
+            None => self.disposer_map.remove(&key),
+        };
+        self.eager_map.remove(&key);
+        self.with_args.remove(&key);
+        self.provider_map.insert(
+            key,
+            Registration {
+                kind: registration.kind,
+                provider: Arc::new(Self::get_async_arc(registration.provider))
+                    as Arc<dyn Any + Send + Sync>,
+            },
+        );
+        self.type_names.insert(key, std::any::type_name::<T>());
+        self.dependency_map.insert(key, deps);
+        #[cfg(feature = "debug")]
+        self.decorator_map.remove(&key);
+        self
+    }
+
+    /// Register a `ProvideWith` provider for `T` with identifier `key`, for types that need
+    /// arguments from the caller on top of their injected dependencies. Keys registered this way
+    /// are always transient, and can only be resolved through [`Container::resolve_with`].
+    ///
+    /// [`Container::resolve_with`]: struct.Container.html#method.resolve_with
+    #[track_caller]
+    pub fn register_with<P, T, Args>(mut self, key: &'static str, provider: P) -> Self
+    where
+        T: Inject + ?Sized,
+        Args: 'static,
+        P: ProvideWith<Args, Output = T> + Send + Sync + 'static,
+    {
+        let key = self.qualify(Key::named(key));
+        if !self.admit(key, Location::caller()) {
+            return self;
+        }
+        self.record_module(key);
+        self.instance_map.remove(&key);
+        self.disposer_map.remove(&key);
+        self.eager_map.remove(&key);
+        self.with_args.insert(key);
+        self.type_names.insert(key, std::any::type_name::<T>());
+        self.dependency_map.insert(
+            key,
+            Dependencies {
+                required: provider.dependencies(),
+                optional: provider.optional_dependencies(),
+            },
+        );
+        #[cfg(feature = "debug")]
+        self.decorator_map.remove(&key);
+        let provider: Arc<dyn ProvideWith<Args, Output = T> + Send + Sync> = Arc::new(provider);
+        self.provider_map.insert(
+            key,
+            Registration {
+                kind: RegistrationKind::Transient,
+                provider: Arc::new(provider) as Arc<dyn Any + Send + Sync>,
+            },
+        );
+        self
+    }
+
+    /// Consume this builder to produce a `Container`, constructing every singleton up front so
+    /// that misconfigured ones are reported here rather than on their first [`resolve`].
+    ///
+    /// Resolving a singleton resolves its dependencies first, so they're constructed in
+    /// dependency order. Construction continues past failures, and every failure is collected
+    /// into [`Error::Eager`]. Singletons registered with an `AsyncProvide`r can't be constructed
+    /// here, and are still resolved on first use.
+    ///
+    /// ```rust
+    /// use coi::{ContainerBuilder, Error, Inject, Registration, RegistrationKind};
+    /// # use std::sync::Arc;
+    ///
+    /// #[derive(Inject)]
+    /// #[coi(provides Client with Client { config })]
+    /// struct Client {
+    ///     #[coi(inject)]
+    ///     config: Arc<Config>,
+    /// }
+    ///
+    /// #[derive(Inject)]
+    /// struct Config;
+    ///
+    /// let result = ContainerBuilder::new()
+    ///     .register_as(
+    ///         "client",
+    ///         Registration::new(RegistrationKind::Singleton, ClientProvider),
+    ///     )
+    ///     .build_eager();
+    /// match result {
+    ///     Err(Error::Eager(errors)) => assert_eq!(errors[0].0, "client"),
+    ///     _ => panic!("`config` was never registered"),
+    /// }
+    /// ```
+    ///
+    /// [`resolve`]: struct.Container.html#method.resolve
+    /// [`Error::Eager`]: enum.Error.html#variant.Eager
+    pub fn build_eager(mut self) -> Result<Container> {
+        let eager = std::mem::take(&mut self.eager_map);
+        let container = self.try_build()?;
+        let errors: Vec<_> = eager
+            .into_iter()
+            .filter_map(|(key, resolve)| {
+                // Singletons registered by a module are resolved the way its own providers would
+                // resolve them, so that the keys it didn't export are constructed too.
+                let module = container
+                    .inner
+                    .registrations
+                    .get(key)
+                    .and_then(|(_, entry)| entry.module)
+                    .map(|entry| entry.module);
+                let owner = Container {
+                    inner: Arc::clone(&container.inner),
+                    chain: None,
+                    module,
+                };
+                resolve(&owner, key).err().map(|e| (key.to_string(), e))
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(container)
+        } else {
+            Err(Error::Eager(errors))
+        }
+    }
+
+    /// Consume this builder to produce a `Container`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`try_build`] would fail: if any key was registered more than once with
+    /// [`DuplicatePolicy::Error`], or if a module was added without the modules it depends on,
+    /// registered another module's keys, or exports keys it never registered. Use [`try_build`]
+    /// to handle that instead.
+    ///
+    /// [`DuplicatePolicy::Error`]: enum.DuplicatePolicy.html#variant.Error
+    /// [`try_build`]: #method.try_build
+    pub fn build(self) -> Container {
+        match self.try_build() {
+            Ok(container) => container,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Consume this builder to produce a `Container`, failing with [`Error::DuplicateKeys`] if
+    /// any key was registered more than once with [`DuplicatePolicy::Error`]. Each duplicate
+    /// lists where both registrations were made.
+    ///
+    /// Also fails if the [`Module`]s added don't fit together: with [`Error::MissingModules`] if
+    /// a module's dependencies weren't added, [`Error::ForeignModuleKeys`] if a module registered
+    /// keys qualified with another module's name, and [`Error::UnknownExports`] if a module
+    /// exports keys it never registered.
+    ///
+    /// [`Error::DuplicateKeys`]: enum.Error.html#variant.DuplicateKeys
+    /// [`DuplicatePolicy::Error`]: enum.DuplicatePolicy.html#variant.Error
+    /// [`Module`]: trait.Module.html
+    /// [`Error::MissingModules`]: enum.Error.html#variant.MissingModules
+    /// [`Error::ForeignModuleKeys`]: enum.Error.html#variant.ForeignModuleKeys
+    /// [`Error::UnknownExports`]: enum.Error.html#variant.UnknownExports
+    pub fn try_build(self) -> Result<Container> {
+        if !self.duplicates.is_empty() {
+            return Err(Error::DuplicateKeys(self.duplicates));
+        }
+
+        let missing: Vec<_> = self
+            .modules
+            .iter()
+            .flat_map(|&(module, dependencies, _)| {
+                dependencies
+                    .iter()
+                    .filter(|dependency| {
+                        !self.modules.iter().any(|(name, _, _)| name == *dependency)
+                    })
+                    .map(move |dependency| (module.to_owned(), dependency.to_string()))
+            })
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::MissingModules(missing));
+        }
+
+        if !self.foreign_keys.is_empty() {
+            return Err(Error::ForeignModuleKeys(self.foreign_keys));
+        }
+
+        let module_map = &self.module_map;
+        let unknown: Vec<_> = self
+            .modules
+            .iter()
+            .flat_map(|&(module, _, exports)| {
+                exports
+                    .iter()
+                    .filter(move |export| {
+                        let key = Key::Module(module, export);
+                        !matches!(module_map.get(&key), Some(entry) if entry.module == module)
+                    })
+                    .map(move |export| (module.to_owned(), export.to_string()))
+            })
+            .collect();
+        if !unknown.is_empty() {
+            return Err(Error::UnknownExports(unknown));
+        }
+
+        let (registrations, instances) = self.into_registrations(None);
+        let container = InnerContainer::new(Arc::new(registrations), true, None);
+        Ok(Container::new(container.with_instances(instances)))
+    }
+
+    /// Register the singletons of `base` that depend on a key registered here again, either
+    /// directly or through other registrations, so that the container built from this constructs
+    /// its own instances of them, using the keys registered here.
+    fn inherit_dependents(&mut self, base: &Registrations) {
+        // A collection counts as overridden when any of its providers is.
+        let overridden = |key: &Key| match key {
+            Key::Item(name, _) => vec![*key, Key::Name(name)],
+            _ => vec![*key],
+        };
+        let mut affected = self
+            .provider_map
+            .keys()
+            .flat_map(overridden)
+            .collect::<HashSet<_>>();
+        let entries = base.entries();
+        loop {
+            let dependents = entries
+                .iter()
+                .filter(|(key, layer, entry)| {
+                    if affected.contains(key) {
+                        return false;
+                    }
+                    let deps = match layer.dependency_map.get(key) {
+                        Some(deps) => deps,
+                        None => return false,
+                    };
+                    let required = deps.required.unwrap_or(&[]).iter();
+                    required.chain(deps.optional).any(|dep| {
+                        // Dependencies of a module's providers refer to the module's own keys
+                        // first.
+                        let dep = match (Key::named(dep), entry.module) {
+                            (Key::Name(name), Some(entry))
+                                if base.get(Key::Module(entry.module, name)).is_some()
+                                    || self
+                                        .provider_map
+                                        .contains_key(&Key::Module(entry.module, name)) =>
+                            {
+                                Key::Module(entry.module, name)
+                            }
+                            (dep, _) => dep,
+                        };
+                        affected.contains(&dep)
+                    })
+                })
+                .map(|(key, ..)| *key)
+                .collect::<Vec<_>>();
+            if dependents.is_empty() {
+                break;
+            }
+            affected.extend(dependents.iter().flat_map(overridden));
+        }
+
+        for (key, layer, entry) in entries {
+            if !affected.contains(&key)
+                || self.provider_map.contains_key(&key)
+                || !matches!(entry.registration.kind, RegistrationKind::Singleton)
+            {
+                continue;
+            }
+            self.provider_map.insert(key, entry.registration.clone());
+            self.type_names.insert(key, entry.type_name);
+            if let Some(deps) = layer.dependency_map.get(&key) {
+                self.dependency_map.insert(key, *deps);
+            }
+            if let Some(disposer) = &entry.disposer {
+                self.disposer_map.insert(key, disposer.clone());
+            }
+            if let Some(module) = entry.module {
+                self.module_map.insert(key, module);
+            }
+            #[cfg(feature = "debug")]
+            if let Some(count) = layer.decorator_map.get(&key) {
+                self.decorator_map.insert(key, *count);
+            }
+        }
+    }
+
+    /// Split this builder into the registrations of a container, added on top of `base`, and
+    /// the instances that container caches from the start.
+    fn into_registrations(
+        mut self,
+        base: Option<Arc<Registrations>>,
+    ) -> (Registrations, HashMap<Key, Arc<Resolved>>) {
+        // Scoped instances are cached by every scope, and come first so that a scope doesn't need
+        // slots for the singletons.
+        let scoped_start = base.as_ref().map_or(0, |base| base.scoped_slots);
+        let scoped_slots = scoped_start
+            + self
+                .provider_map
+                .values()
+                .filter(|registration| matches!(registration.kind, RegistrationKind::Scoped))
+                .count();
+        let (mut next_scoped, mut next_singleton) = (scoped_start, scoped_slots);
+        let mut entries = HashMap::default();
+        for (key, registration) in self.provider_map {
+            let next = match registration.kind {
+                RegistrationKind::Scoped => Some(&mut next_scoped),
+                RegistrationKind::Singleton => Some(&mut next_singleton),
+                RegistrationKind::Transient => None,
+            };
+            let slot = next.map(|next| {
+                *next += 1;
+                *next - 1
+            });
+            let entry = RegistrationEntry {
+                registration,
+                type_name: self.type_names.remove(&key).unwrap_or_default(),
+                disposer: self.disposer_map.remove(&key),
+                module: self.module_map.get(&key).copied(),
+                slot,
+                with_args: self.with_args.contains(&key),
+                #[cfg(feature = "debug")]
+                instance: self.instance_map.contains_key(&key),
+            };
+            entries.insert(key, entry);
+        }
+
+        let check_captive =
+            self.check_captive_dependencies || base.as_ref().is_some_and(|base| base.check_captive);
+        let mut observers = base
+            .as_ref()
+            .map_or_else(Observers::default, |base| base.observers.clone());
+        observers.0.extend(self.observers);
+        let registrations = Registrations {
+            entries,
+            collection_map: self.collection_map,
+            check_captive,
+            observers,
+            base,
+            scoped_slots,
+            slots: next_singleton,
+            dependency_map: self.dependency_map,
+            #[cfg(feature = "debug")]
+            decorator_map: self.decorator_map,
+        };
+        (registrations, self.instance_map)
+    }
+}
+
+/// Provides an instance registered with `ContainerBuilder::register_instance`. Only needed if the
+/// instance stops being cached, since the container is built with it already resolved.
+struct InstanceProvider<T: ?Sized>(Arc<T>);
+
+impl<T> Provide for InstanceProvider<T>
+where
+    T: Inject + ?Sized,
+{
+    type Output = T;
+
+    fn provide(&self, _: &Container) -> Result<Arc<T>> {
+        Ok(Arc::clone(&self.0))
+    }
+
+    fn dependencies(&self) -> Option<&'static [&'static str]> {
+        Some(&[])
+    }
+}
+
+/// The provider of a key decorated with `ContainerBuilder::decorate`, passing every instance
+/// `inner` provides through `decorator`.
+struct Decorated<P: ?Sized, F> {
+    inner: Arc<P>,
+    decorator: F,
+}
+
+impl<T, F> Provide for Decorated<dyn Provide<Output = T> + Send + Sync, F>
+where
+    T: Inject + ?Sized,
+    F: Fn(Arc<T>, &Container) -> Result<Arc<T>>,
+{
+    type Output = T;
+
+    fn provide(&self, container: &Container) -> Result<Arc<T>> {
+        let inner = self.inner.provide(container)?;
+        (self.decorator)(inner, container)
+    }
+
+    fn dependencies(&self) -> Option<&'static [&'static str]> {
+        self.inner.dependencies()
+    }
+
+    fn optional_dependencies(&self) -> &'static [&'static str] {
+        self.inner.optional_dependencies()
+    }
+}
+
+impl<T, F> AsyncProvide for Decorated<dyn AsyncProvide<Output = T> + Send + Sync, F>
+where
+    T: Inject + ?Sized,
+    F: Fn(Arc<T>, &Container) -> Result<Arc<T>> + Send + Sync,
+{
+    type Output = T;
+
+    fn provide<'a>(&'a self, container: &'a Container) -> BoxFuture<'a, Result<Arc<T>>> {
+        Box::pin(async move {
+            let inner = self.inner.provide(container).await?;
+            (self.decorator)(inner, container)
+        })
+    }
+
+    fn dependencies(&self) -> Option<&'static [&'static str]> {
+        self.inner.dependencies()
+    }
+
+    fn optional_dependencies(&self) -> &'static [&'static str] {
+        self.inner.optional_dependencies()
+    }
+}
+
+type ResolveErased = fn(&Container, Key) -> Result<()>;
+
+/// Resolves `key` as a `T`, discarding the result. Stored as a fn pointer so that registrations
+/// can be resolved without knowing their type.
+fn resolve_erased<T>(container: &Container, key: Key) -> Result<()>
+where
+    T: Inject + ?Sized,
+{
+    container.resolve_key::<T>(key).map(|_| ())
+}
+
+/// A trait to observe the resolutions made by a container and its scopes, e.g. to log and time
+/// every construction. Observers are added with [`ContainerBuilder::add_observer`], and every
+/// method defaults to doing nothing.
+///
+/// Each key resolved, including the dependencies resolved by providers, is reported by
+/// [`resolve_started`] followed by one of [`cache_hit`], [`provider_finished`] or
+/// [`resolve_failed`]. Keys are reported the way they're displayed in errors. Observers are
+/// called on the resolving thread, so they should return quickly.
+///
+/// ```rust
+/// use coi::{ContainerBuilder, Inject, RegistrationKind, ResolveObserver};
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+///
+/// #[derive(Default)]
+/// struct Timings(Mutex<Vec<(String, Duration)>>);
+///
+/// impl ResolveObserver for Timings {
+///     fn provider_finished(&self, key: &str, _: RegistrationKind, duration: Duration) {
+///         self.0.lock().unwrap().push((key.to_owned(), duration));
+///     }
+/// }
+///
+/// #[derive(Inject)]
+/// #[coi(provides Pool with Pool)]
+/// struct Pool;
+///
+/// let timings = Arc::new(Timings::default());
+/// let container = ContainerBuilder::new()
+///     .register("pool", PoolProvider)
+///     .add_observer(Arc::clone(&timings))
+///     .build();
+/// container.resolve::<Pool>("pool").unwrap();
+/// assert_eq!(timings.0.lock().unwrap()[0].0, "pool");
+/// ```
+///
+/// [`ContainerBuilder::add_observer`]: struct.ContainerBuilder.html#method.add_observer
+/// [`resolve_started`]: #method.resolve_started
+/// [`cache_hit`]: #method.cache_hit
+/// [`provider_finished`]: #method.provider_finished
+/// [`resolve_failed`]: #method.resolve_failed
+pub trait ResolveObserver: Send + Sync {
+    /// Resolving `key` started.
+    fn resolve_started(&self, key: &str) {
+        let _ = key;
+    }
+
+    /// `key` was resolved to an instance its container had already cached.
+    fn cache_hit(&self, key: &str, kind: RegistrationKind) {
+        let _ = (key, kind);
+    }
+
+    /// The provider of `key` constructed an instance, taking `duration`. For providers that
+    /// resolve dependencies, this includes resolving them.
+    fn provider_finished(&self, key: &str, kind: RegistrationKind, duration: Duration) {
+        let _ = (key, kind, duration);
+    }
+
+    /// Resolving `key` failed with `error`. Failures propagate, so each key on the resolution
+    /// path that led to the error reports it too.
+    fn resolve_failed(&self, key: &str, error: &Error) {
+        let _ = (key, error);
+    }
+}
+
+impl<O> ResolveObserver for Arc<O>
+where
+    O: ResolveObserver + ?Sized,
+{
+    fn resolve_started(&self, key: &str) {
+        (**self).resolve_started(key)
+    }
+
+    fn cache_hit(&self, key: &str, kind: RegistrationKind) {
+        (**self).cache_hit(key, kind)
+    }
+
+    fn provider_finished(&self, key: &str, kind: RegistrationKind, duration: Duration) {
+        (**self).provider_finished(key, kind, duration)
+    }
+
+    fn resolve_failed(&self, key: &str, error: &Error) {
+        (**self).resolve_failed(key, error)
+    }
+}
+
+/// The observers added to a container. Nothing is formatted or timed unless there are any.
+///
+/// With the "tracing" feature, what they're notified of is also recorded on the span of the
+/// resolution, see `resolve_span`.
+#[derive(Clone, Default)]
+struct Observers(Vec<Arc<dyn ResolveObserver>>);
+
+impl Observers {
+    fn each(&self, key: Key, notify: impl Fn(&dyn ResolveObserver, &str)) {
+        if self.0.is_empty() {
+            return;
+        }
+        let key = key.to_string();
+        for observer in &self.0 {
+            notify(&**observer, &key);
+        }
+    }
+
+    fn started(&self, key: Key) {
+        self.each(key, |observer, key| observer.resolve_started(key));
+    }
+
+    fn cache_hit(&self, key: Key, entry: &RegistrationEntry) {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("cached", true);
+        let kind = entry.registration.kind;
+        self.each(key, |observer, key| observer.cache_hit(key, kind));
+    }
+
+    /// When a provider started running, if anything is timing it.
+    fn timer(&self) -> Option<Instant> {
+        (!self.0.is_empty()).then(Instant::now)
+    }
+
+    fn provided(&self, key: Key, entry: &RegistrationEntry, timer: Option<Instant>) {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("cached", false);
+        if let Some(timer) = timer {
+            let (kind, duration) = (entry.registration.kind, timer.elapsed());
+            self.each(key, |observer, key| {
+                observer.provider_finished(key, kind, duration)
+            });
+        }
+    }
+
+    /// Report `resolved` if it's an error, and pass it on.
+    fn failed<T>(&self, key: Key, resolved: Result<T>) -> Result<T> {
+        if let Err(e) = &resolved {
+            #[cfg(feature = "tracing")]
+            tracing::Span::current().record("error", tracing::field::display(e));
+            self.each(key, |observer, key| observer.resolve_failed(key, e));
+        }
+        resolved
+    }
+}
+
+/// The span a container resolves `key` as a `T` in, along with the `kind` of `entry` if it was
+/// found. Resolving a dependency happens inside the span of the key depending on it, so spans are
+/// nested the same way the keys are. Whether the instance was `cached` and the `error` it failed
+/// with, if any, are recorded once they're known.
+#[cfg(feature = "tracing")]
+fn resolve_span<T>(key: Key, entry: Option<&RegistrationEntry>) -> tracing::Span
+where
+    T: Inject + ?Sized,
+{
+    let span = tracing::debug_span!(
+        "resolve",
+        key = %key,
+        type_name = std::any::type_name::<T>(),
+        kind = tracing::field::Empty,
+        cached = tracing::field::Empty,
+        error = tracing::field::Empty,
+    );
+    if let Some(entry) = entry {
+        span.record("kind", tracing::field::debug(entry.registration.kind));
+    }
+    span
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} observers", self.0.len())
+    }
+}
+
+/// A trait to manage the construction of an injectable trait or struct.
+pub trait Provide {
+    /// The type that this provider will produce when resolved from a [`Container`].
+    ///
+    /// [`Container`]: struct.Container.html
+    type Output: Inject + ?Sized;
+
+    /// Only intended to be used internally
+    fn provide(&self, container: &Container) -> Result<Arc<Self::Output>>;
+
+    /// Return list of dependencies, or `None` if they aren't known. Only used by the "debug"
+    /// feature, where [`Container::analyze`] can't check the dependencies of providers that
+    /// return `None`, like plain closures. Defaults to `None`.
+    ///
+    /// [`Container::analyze`]: struct.Container.html#method.analyze
+    fn dependencies(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
+    /// Return list of dependencies that are resolved with [`Container::try_resolve`], and so
+    /// don't need to be registered. Only used by the "debug" feature.
+    ///
+    /// [`Container::try_resolve`]: struct.Container.html#method.try_resolve
+    fn optional_dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// How scoped and singleton instances of `Output` are disposed of when the container that
+    /// owns them is disposed or dropped. See [`Disposer`].
+    ///
+    /// [`Disposer`]: enum.Disposer.html
+    fn disposer(&self) -> Option<Disposer<Self::Output>> {
+        None
+    }
+}
+
+/// A trait to manage the construction of an injectable trait or struct from arguments supplied
+/// by the caller, on top of the dependencies it resolves from the container. `Args` is usually a
+/// tuple when there's more than one argument.
+///
+/// Providers of this kind are registered with [`ContainerBuilder::register_with`] and resolved
+/// with [`Container::resolve_with`]. Deriving `Inject` on a struct with `#[coi(arg)]` fields
+/// generates one.
+///
+/// [`ContainerBuilder::register_with`]: struct.ContainerBuilder.html#method.register_with
+/// [`Container::resolve_with`]: struct.Container.html#method.resolve_with
+pub trait ProvideWith<Args> {
+    /// The type that this provider will produce when resolved from a [`Container`].
+    ///
+    /// [`Container`]: struct.Container.html
+    type Output: Inject + ?Sized;
+
+    /// Only intended to be used internally
+    fn provide_with(&self, container: &Container, args: Args) -> Result<Arc<Self::Output>>;
+
+    /// Return list of dependencies, or `None` if they aren't known. See
+    /// [`Provide::dependencies`].
+    ///
+    /// [`Provide::dependencies`]: trait.Provide.html#method.dependencies
+    fn dependencies(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
+    /// Return list of dependencies that are resolved with [`Container::try_resolve`], and so
+    /// don't need to be registered. Only used by the "debug" feature.
+    ///
+    /// [`Container::try_resolve`]: struct.Container.html#method.try_resolve
+    fn optional_dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+}
+
+/// A trait to manage the asynchronous construction of an injectable trait or struct.
+///
+/// Use this over [`Provide`] when constructing `Output` requires awaiting, e.g. connecting to a
+/// database. Providers of this kind are registered with [`ContainerBuilder::register_async_as`]
+/// and resolved with [`Container::resolve_async`].
+///
+/// ```rust
+/// use coi::{AsyncProvide, BoxFuture, Container, Inject};
+/// use std::sync::Arc;
+///
+/// # struct Config;
+/// # impl Inject for Config {}
+/// struct Pool;
+///
+/// impl Inject for Pool {}
+///
+/// impl Pool {
+///     async fn connect(_config: Arc<Config>) -> Self {
+///         Pool
+///     }
+/// }
+///
+/// struct PoolProvider;
+///
+/// impl AsyncProvide for PoolProvider {
+///     type Output = Pool;
+///
+///     fn provide<'a>(&'a self, container: &'a Container) -> BoxFuture<'a, coi::Result<Arc<Pool>>> {
+///         Box::pin(async move {
+///             let config = container.resolve_async::<Config>("config").await?;
+///             Ok(Arc::new(Pool::connect(config).await))
+///         })
+///     }
+/// }
+/// ```
+///
+/// [`Provide`]: trait.Provide.html
+/// [`ContainerBuilder::register_async_as`]: struct.ContainerBuilder.html#method.register_async_as
+/// [`Container::resolve_async`]: struct.Container.html#method.resolve_async
+pub trait AsyncProvide {
+    /// The type that this provider will produce when resolved from a [`Container`].
+    ///
+    /// [`Container`]: struct.Container.html
+    type Output: Inject + ?Sized;
+
+    /// Only intended to be used internally
+    fn provide<'a>(&'a self, container: &'a Container) -> BoxFuture<'a, Result<Arc<Self::Output>>>;
+
+    /// Return list of dependencies, or `None` if they aren't known. Only used by the "debug"
+    /// feature, where [`Container::analyze`] can't check the dependencies of providers that
+    /// return `None`, like plain closures. Defaults to `None`.
+    ///
+    /// [`Container::analyze`]: struct.Container.html#method.analyze
+    fn dependencies(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
+    /// Return list of dependencies that are resolved with [`Container::try_resolve`], and so
+    /// don't need to be registered. Only used by the "debug" feature.
+    ///
+    /// [`Container::try_resolve`]: struct.Container.html#method.try_resolve
+    fn optional_dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// How scoped and singleton instances of `Output` are disposed of when the container that
+    /// owns them is disposed or dropped. See [`Disposer`].
+    ///
+    /// [`Disposer`]: enum.Disposer.html
+    fn disposer(&self) -> Option<Disposer<Self::Output>> {
+        None
+    }
+}
+
+/// A trait for injected types that need to release resources, like committing or rolling back a
+/// transaction, when the container that owns them goes away.
+///
+/// A provider opts its instances into this by returning [`Disposer::Sync`] from
+/// [`Provide::disposer`]. See [`Container::dispose`] for when it's called. Dropping the last
+/// handle to the container calls it too, but any error it returns is lost.
+///
+/// [`Disposer::Sync`]: enum.Disposer.html#variant.Sync
+/// [`Provide::disposer`]: trait.Provide.html#method.disposer
+/// [`Container::dispose`]: struct.Container.html#method.dispose
+pub trait Dispose {
+    /// Release the resources held by this instance.
+    fn dispose(&self) -> Result<()>;
+}
+
+/// The asynchronous version of [`Dispose`], for instances that need to await while releasing
+/// their resources.
+///
+/// A provider opts its instances into this by returning [`Disposer::Async`] from
+/// [`Provide::disposer`]. Such instances can only be disposed of through
+/// [`Container::dispose_async`]: [`Container::dispose`] leaves them in the container, and
+/// dropping the last handle to the container drops them *without* disposing of them, since the
+/// disposer can't be awaited from `Drop`. With the "tracing" feature, a warning is logged for each
+/// of those.
+///
+/// [`Container::dispose`]: struct.Container.html#method.dispose
+/// [`Dispose`]: trait.Dispose.html
+/// [`Disposer::Async`]: enum.Disposer.html#variant.Async
+/// [`Provide::disposer`]: trait.Provide.html#method.disposer
+/// [`Container::dispose_async`]: struct.Container.html#method.dispose_async
+pub trait AsyncDispose {
+    /// Release the resources held by this instance.
+    fn dispose<'a>(&'a self) -> BoxFuture<'a, Result<()>>;
+}
+
+/// How a provider's instances are disposed of, returned from [`Provide::disposer`] and
+/// [`AsyncProvide::disposer`].
+///
+/// ```rust
+/// use coi::{container, Container, Dispose, Disposer, Inject, Provide};
+/// use std::sync::{
+///     atomic::{AtomicBool, Ordering},
+///     Arc,
+/// };
+///
+/// static COMMITTED: AtomicBool = AtomicBool::new(false);
+///
+/// struct Transaction;
+///
+/// impl Inject for Transaction {}
+///
+/// impl Dispose for Transaction {
+///     fn dispose(&self) -> coi::Result<()> {
+///         COMMITTED.store(true, Ordering::SeqCst);
+///         Ok(())
+///     }
+/// }
+///
+/// struct TransactionProvider;
+///
+/// impl Provide for TransactionProvider {
+///     type Output = Transaction;
+///
+///     fn provide(&self, _: &Container) -> coi::Result<Arc<Transaction>> {
+///         Ok(Arc::new(Transaction))
+///     }
+///
+///     fn disposer(&self) -> Option<Disposer<Transaction>> {
+///         Some(Disposer::Sync(Transaction::dispose))
+///     }
+/// }
+///
+/// let container = container! {
+///     tx => TransactionProvider; scoped,
+/// };
+/// let scope = container.scoped();
+/// let _tx = scope.resolve::<Transaction>("tx").unwrap();
+/// scope.dispose().unwrap();
+/// assert!(COMMITTED.load(Ordering::SeqCst));
+/// ```
+///
+/// [`Provide::disposer`]: trait.Provide.html#method.disposer
+/// [`AsyncProvide::disposer`]: trait.AsyncProvide.html#method.disposer
+pub enum Disposer<T: ?Sized> {
+    /// Dispose of instances by calling this fn, usually [`Dispose::dispose`].
+    ///
+    /// [`Dispose::dispose`]: trait.Dispose.html#tymethod.dispose
+    Sync(fn(&T) -> Result<()>),
+    /// Dispose of instances by awaiting this fn, usually [`AsyncDispose::dispose`].
+    ///
+    /// [`AsyncDispose::dispose`]: trait.AsyncDispose.html#tymethod.dispose
+    Async(for<'a> fn(&'a T) -> BoxFuture<'a, Result<()>>),
+}
+
+type SyncDisposeFn = dyn Fn(&(dyn Any + Send + Sync)) -> Result<()> + Send + Sync;
+type AsyncDisposeFn = dyn Fn(Arc<Resolved>) -> BoxFuture<'static, Result<()>> + Send + Sync;
+// An instance removed from its container to be disposed of, along with its key.
+type Disposable = (Key, Arc<Resolved>, AnyDisposer);
+
+/// A `Disposer` for instances stored in a `Slot`.
+#[derive(Clone)]
+enum AnyDisposer {
+    Sync(Arc<SyncDisposeFn>),
+    Async(Arc<AsyncDisposeFn>),
+}
+
+impl AnyDisposer {
+    fn new<T>(disposer: Disposer<T>) -> Self
+    where
+        T: Inject + ?Sized,
+    {
+        match disposer {
+            Disposer::Sync(dispose) => AnyDisposer::Sync(Arc::new(move |resolved| {
+                dispose(resolved.downcast_ref::<Arc<T>>().unwrap())
+            })),
+            Disposer::Async(dispose) => AnyDisposer::Async(Arc::new(move |resolved| {
+                Box::pin(async move {
+                    let resolved = Arc::clone(resolved.downcast_ref::<Arc<T>>().unwrap());
+                    dispose(&resolved).await
+                })
+            })),
+        }
+    }
+}
+
+impl fmt::Debug for AnyDisposer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnyDisposer::Sync(_) => f.write_str("Sync"),
+            AnyDisposer::Async(_) => f.write_str("Async"),
+        }
+    }
+}
+
+impl<T, F> Provide for F
+where
+    F: Fn(&Container) -> Result<Arc<T>>,
+    T: Inject + ?Sized,
+{
+    type Output = T;
 
     fn provide(&self, container: &Container) -> Result<Arc<Self::Output>> {
         self(container)
     }
 }
 
-#[cfg(feature = "debug")]
-#[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
+impl<T> Provide for dyn Fn(&Container) -> Result<Arc<T>>
+where
+    T: Inject + ?Sized,
+{
+    type Output = T;
+
+    fn provide(&self, container: &Container) -> Result<Arc<Self::Output>> {
+        self(container)
+    }
+}
+
+/// A closure along with the keys it resolves, as produced by [`provide_closure`].
+///
+/// [`provide_closure`]: macro.provide_closure.html
 impl<T, F> Provide for (&'static [&'static str], F)
 where
     F: Fn(&Container) -> Result<Arc<T>>,
@@ -896,13 +4778,11 @@ where
         (self.1)(container)
     }
 
-    fn dependencies(&self) -> &'static [&'static str] {
-        self.0
+    fn dependencies(&self) -> Option<&'static [&'static str]> {
+        Some(self.0)
     }
 }
 
-#[cfg(feature = "debug")]
-#[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
 impl<T> Provide
     for (
         &'static [&'static str],
@@ -917,8 +4797,8 @@ where
         (self.1)(container)
     }
 
-    fn dependencies(&self) -> &'static [&'static str] {
-        self.0
+    fn dependencies(&self) -> Option<&'static [&'static str]> {
+        Some(self.0)
     }
 }
 
@@ -948,7 +4828,43 @@ where
 ///
 /// For details on how each registration works, see [`coi::Registration`]
 ///
+/// Already constructed instances are registered with `instance(...)`, which takes an `Arc`:
+/// ```rust
+/// use coi::{container, Inject};
+/// use std::sync::Arc;
+///
+/// struct Config {
+///     port: u16,
+/// }
+///
+/// impl Inject for Config {}
+///
+/// let container = container! {
+///     config => instance(Arc::new(Config { port: 8080 })),
+/// };
+/// assert_eq!(container.resolve::<Config>("config").unwrap().port, 8080);
+/// ```
+///
+/// Instances are always shared like singletons, so giving one a `; kind` fails to compile. Any
+/// entry written as `instance(..)` is registered this way, while other expressions, including a
+/// variable that happens to be named `instance`, are providers. See
+/// [`ContainerBuilder::register_instance`] for how they're resolved.
+///
+/// ```rust,compile_fail
+/// use coi::{container, Inject};
+/// use std::sync::Arc;
+///
+/// struct Config;
+///
+/// impl Inject for Config {}
+///
+/// let container = container! {
+///     config => instance(Arc::new(Config)); scoped,
+/// };
+/// ```
+///
 /// [`coi::Registration`]: enum.Registration.html
+/// [`ContainerBuilder::register_instance`]: struct.ContainerBuilder.html#method.register_instance
 #[macro_export]
 macro_rules! container {
     (@registration $provider:expr; scoped) => {
@@ -975,27 +4891,106 @@ macro_rules! container {
             $provider
         )
     };
-    (@line $builder:ident $key:ident $provider:expr $(; $call:ident)?) => {
+    (@entry $builder:ident $key:ident $provider:expr $(; $call:ident)?) => {
         $builder = $builder.register_as(stringify!($key), container!(@registration $provider $(; $call)?));
     };
-    ($($key:ident => $provider:expr $(; $call:ident)?),+) => {
-        container!{ $( $key => $provider $(; $call)?, )+ }
+    // Entries are taken a few at a time rather than all at once, since `instance(..)` can't be
+    // told apart from a provider once it's been captured as an `expr`. Taking up to four
+    // providers per step keeps long lists well within the recursion limit.
+    (@munch $builder:ident; $(,)?) => {};
+    (@munch $builder:ident; $key:ident => instance($instance:expr); $call:ident, $($rest:tt)*) => {
+        compile_error!(concat!(
+            "`", stringify!($key), " => instance(..)` is always shared, it can't be `",
+            stringify!($call), "`",
+        ));
+    };
+    (@munch $builder:ident; $key:ident => instance($instance:expr), $($rest:tt)*) => {
+        $builder = $builder.register_instance(stringify!($key), $instance);
+        container!(@munch $builder; $($rest)*);
+    };
+    (@munch $builder:ident;
+        $k1:ident => $p1:expr $(; $c1:ident)?,
+        $k2:ident => instance $($rest:tt)*
+    ) => {
+        container!(@entry $builder $k1 $p1 $(; $c1)?);
+        container!(@munch $builder; $k2 => instance $($rest)*);
+    };
+    (@munch $builder:ident;
+        $k1:ident => $p1:expr $(; $c1:ident)?,
+        $k2:ident => $p2:expr $(; $c2:ident)?,
+        $k3:ident => instance $($rest:tt)*
+    ) => {
+        container!(@entry $builder $k1 $p1 $(; $c1)?);
+        container!(@entry $builder $k2 $p2 $(; $c2)?);
+        container!(@munch $builder; $k3 => instance $($rest)*);
+    };
+    (@munch $builder:ident;
+        $k1:ident => $p1:expr $(; $c1:ident)?,
+        $k2:ident => $p2:expr $(; $c2:ident)?,
+        $k3:ident => $p3:expr $(; $c3:ident)?,
+        $k4:ident => instance $($rest:tt)*
+    ) => {
+        container!(@entry $builder $k1 $p1 $(; $c1)?);
+        container!(@entry $builder $k2 $p2 $(; $c2)?);
+        container!(@entry $builder $k3 $p3 $(; $c3)?);
+        container!(@munch $builder; $k4 => instance $($rest)*);
+    };
+    (@munch $builder:ident;
+        $k1:ident => $p1:expr $(; $c1:ident)?,
+        $k2:ident => $p2:expr $(; $c2:ident)?,
+        $k3:ident => $p3:expr $(; $c3:ident)?,
+        $k4:ident => $p4:expr $(; $c4:ident)?,
+        $($rest:tt)*
+    ) => {
+        container!(@entry $builder $k1 $p1 $(; $c1)?);
+        container!(@entry $builder $k2 $p2 $(; $c2)?);
+        container!(@entry $builder $k3 $p3 $(; $c3)?);
+        container!(@entry $builder $k4 $p4 $(; $c4)?);
+        container!(@munch $builder; $($rest)*);
+    };
+    (@munch $builder:ident; $key:ident => $provider:expr $(; $call:ident)?, $($rest:tt)*) => {
+        container!(@entry $builder $key $provider $(; $call)?);
+        container!(@munch $builder; $($rest)*);
     };
-    ($($key:ident => $provider:expr $(; $call:ident)?,)+) => {
+    ($($entries:tt)+) => {
         {
             let mut builder = ::coi::ContainerBuilder::new();
-            $(container!(@line builder $key $provider $(; $call)?);)+
+            container!(@munch builder; $($entries)+,);
             builder.build()
         }
-    }
+    };
 }
 
-/// Helper macro to ease use of "debug" feature when providing closures
+/// Helper macro to provide closures whose arguments are resolved by name. The closure is paired
+/// with the names of its arguments, which become its [`Provide::dependencies`].
+///
+/// ```rust
+/// use coi::{container, provide_closure, Inject};
+/// use std::sync::Arc;
+///
+/// struct Config(u16);
+///
+/// impl Inject for Config {}
+///
+/// struct Server(u16);
+///
+/// impl Inject for Server {}
+///
+/// let container = container! {
+///     config => |_: &coi::Container| Ok(Arc::new(Config(8080))),
+///     server => provide_closure!(|config: Arc<Config>| {
+///         Ok(Arc::new(Server(config.0)))
+///     }),
+/// };
+/// assert_eq!(container.resolve::<Server>("server").unwrap().0, 8080);
+/// ```
+///
+/// [`Provide::dependencies`]: trait.Provide.html#method.dependencies
 #[macro_export]
 macro_rules! provide_closure {
     // Support any comma format
     ($($move:ident)? |$($arg:ident: Arc<$ty:ty>),*| $(-> $res:ty)? $block:block) => {
-        provide_closure!($($move)? |$($arg: Arc<$ty>,)*| $(-> $res)? $block)
+        $crate::provide_closure!($($move)? |$($arg: Arc<$ty>,)*| $(-> $res)? $block)
     };
     // actual macro
     ($($move:ident)? |$($arg:ident: Arc<$ty:ty>,)*| $(-> $res:ty)? $block:block) => {
@@ -1012,23 +5007,10 @@ macro_rules! provide_closure {
 
 #[doc(hidden)]
 #[macro_export]
-#[cfg(not(feature = "debug"))]
-macro_rules! __provide_closure_impl {
-    ($($move:ident)? |$($arg:ident: $ty:ty,)*| $(-> $res:ty)? $block:block) => {
-        $($move)? |_container: &$crate::Container| $(-> $res)? {
-            $(let $arg = _container.resolve::<$ty>(stringify!($arg))?;)*
-            $block
-        }
-    };
-}
-
-#[doc(hidden)]
-#[macro_export]
-#[cfg(feature = "debug")]
 macro_rules! __provide_closure_impl {
     ($($move:ident)? |$($arg:ident: $ty:ty,)*| $(-> $res:ty)? $block:block) => {
         (
-            &[$(stringify!($arg),)*],
+            &[$(stringify!($arg),)*] as &'static [&'static str],
             $($move)? |_container: &$crate::Container| $(-> $res)? {
                 $(let $arg = _container.resolve::<$ty>(stringify!($arg))?;)*
                 $block
@@ -1045,13 +5027,115 @@ mod test {
     fn ensure_display() {
         use std::io;
 
-        let error = Error::KeyNotFound("S".to_owned());
+        let error = Error::KeyNotFound {
+            key: "S".to_owned(),
+            type_name: "dyn T",
+            path: vec!["R".to_owned(), "S".to_owned()],
+        };
+        let displayed = format!("{}", error);
+        assert_eq!(
+            displayed,
+            "Key not found: S (requested as `dyn T`, resolving R -> S)"
+        );
+
+        let error = Error::TypeMismatch {
+            key: "S2".to_owned(),
+            type_name: "dyn T",
+            path: vec!["S2".to_owned()],
+        };
+        let displayed = format!("{}", error);
+        assert_eq!(
+            displayed,
+            "Type mismatch for key: S2 (requested as `dyn T`, resolving S2)"
+        );
+
+        let error = Error::RequiresAsync("S3".to_owned());
+        let displayed = format!("{}", error);
+        assert_eq!(displayed, "Key requires async resolution: S3");
+
+        let error = Error::RequiresArguments("S5".to_owned());
+        let displayed = format!("{}", error);
+        assert_eq!(displayed, "Key requires arguments: S5");
+
+        let error = Error::Cycle(vec!["a".to_owned(), "b".to_owned(), "a".to_owned()]);
+        let displayed = format!("{}", error);
+        assert_eq!(displayed, "Cycle detected: a -> b -> a");
+
+        let error = Error::ContainerDropped("S4".to_owned());
+        let displayed = format!("{}", error);
+        assert_eq!(displayed, "Container dropped before resolving key: S4");
+
+        let error = Error::RequiresAsyncDispose("S6".to_owned());
+        let displayed = format!("{}", error);
+        assert_eq!(displayed, "Key requires async disposal: S6");
+
+        let error = Error::Dispose(vec![
+            ("a".to_owned(), Error::RequiresAsyncDispose("a".to_owned())),
+            ("b".to_owned(), Error::ContainerDropped("c".to_owned())),
+        ]);
+        let displayed = format!("{}", error);
+        assert_eq!(
+            displayed,
+            "Failed to dispose: a (Key requires async disposal: a), b (Container dropped before resolving key: c)"
+        );
+
+        let error = Error::Eager(vec![("a".to_owned(), Error::RequiresAsync("a".to_owned()))]);
+        let displayed = format!("{}", error);
+        assert_eq!(
+            displayed,
+            "Failed to construct singletons: a (Key requires async resolution: a)"
+        );
+
+        let error = Error::MissingModules(vec![("billing".to_owned(), "auth".to_owned())]);
+        let displayed = format!("{}", error);
+        assert_eq!(displayed, "Missing modules: auth (required by billing)");
+
+        let error = Error::ForeignModuleKeys(vec![("auth".to_owned(), "billing::repo".to_owned())]);
+        let displayed = format!("{}", error);
+        assert_eq!(
+            displayed,
+            "Keys registered outside of their module: billing::repo (in auth)"
+        );
+
+        let error = Error::UnknownExports(vec![("auth".to_owned(), "service".to_owned())]);
+        let displayed = format!("{}", error);
+        assert_eq!(
+            displayed,
+            "Exported keys were never registered: service (in auth)"
+        );
+
+        let location = Location::caller();
+        let error = Error::DuplicateKeys(vec![DuplicateKey {
+            key: "a".to_owned(),
+            first: location,
+            second: location,
+        }]);
+        let displayed = format!("{}", error);
+        assert_eq!(
+            displayed,
+            format!(
+                "Duplicate keys: a (registered at {} and {})",
+                location, location
+            )
+        );
+
+        let error = Error::CaptiveDependency {
+            consumer: "a".to_owned(),
+            dependency: "c".to_owned(),
+            path: vec!["a".to_owned(), "b".to_owned(), "c".to_owned()],
+        };
         let displayed = format!("{}", error);
-        assert_eq!(displayed, "Key not found: S");
+        assert_eq!(
+            displayed,
+            "Captive dependency: singleton a depends on scoped c (resolving a -> b -> c)"
+        );
 
-        let error = Error::TypeMismatch("S2".to_owned());
+        let error = Error::ProviderPanicked {
+            key: "S5".to_owned(),
+            message: "oops".to_owned(),
+        };
         let displayed = format!("{}", error);
-        assert_eq!(displayed, "Type mismatch for key: S2");
+        assert_eq!(displayed, "Provider panicked for key: S5 (oops)");
 
         let error = Error::Inner(Box::new(io::Error::new(io::ErrorKind::NotFound, "oh no!")));
         let displayed = format!("{}", error);
@@ -1060,13 +5144,27 @@ mod test {
 
     #[test]
     fn ensure_debug() {
-        let error = Error::KeyNotFound("S".to_owned());
+        let error = Error::KeyNotFound {
+            key: "S".to_owned(),
+            type_name: "dyn T",
+            path: vec!["S".to_owned()],
+        };
         let debugged = format!("{:?}", error);
-        assert_eq!(debugged, "KeyNotFound(\"S\")");
+        assert_eq!(
+            debugged,
+            "KeyNotFound { key: \"S\", type_name: \"dyn T\", path: [\"S\"] }"
+        );
 
-        let error = Error::TypeMismatch("S2".to_owned());
+        let error = Error::TypeMismatch {
+            key: "S2".to_owned(),
+            type_name: "dyn T",
+            path: vec!["S2".to_owned()],
+        };
         let debugged = format!("{:?}", error);
-        assert_eq!(debugged, "TypeMismatch(\"S2\")");
+        assert_eq!(
+            debugged,
+            "TypeMismatch { key: \"S2\", type_name: \"dyn T\", path: [\"S2\"] }"
+        );
     }
 
     #[test]